@@ -0,0 +1,3 @@
+ALTER TABLE grp
+DROP elo_k_factor,
+DROP elo_initial_rating;
@@ -0,0 +1,3 @@
+ALTER TABLE grp
+ADD elo_k_factor REAL NOT NULL DEFAULT 32,
+ADD elo_initial_rating REAL NOT NULL DEFAULT 1500;
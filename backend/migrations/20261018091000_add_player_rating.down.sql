@@ -0,0 +1 @@
+DROP TABLE public.player_rating;
@@ -0,0 +1,23 @@
+CREATE TABLE
+  public.player_rating (
+    player_id INT NOT NULL,
+    group_id INT NOT NULL,
+    rating DOUBLE PRECISION NOT NULL,
+    deviation DOUBLE PRECISION NOT NULL,
+    volatility DOUBLE PRECISION NOT NULL
+  );
+
+ALTER TABLE
+  public.player_rating
+ADD
+  CONSTRAINT player_rating_pkey PRIMARY KEY (player_id, group_id);
+
+ALTER TABLE
+  public.player_rating
+ADD
+  CONSTRAINT fk_player_id FOREIGN KEY (player_id) REFERENCES player(id);
+
+ALTER TABLE
+  public.player_rating
+ADD
+  CONSTRAINT fk_group_id FOREIGN KEY (group_id) REFERENCES grp(id);
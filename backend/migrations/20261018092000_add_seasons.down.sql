@@ -0,0 +1,3 @@
+DROP TABLE public.season_standing;
+
+DROP TABLE public.season;
@@ -0,0 +1,54 @@
+CREATE TABLE
+  public.season (
+    id serial NOT NULL,
+    group_id INT NOT NULL,
+    name text NOT NULL,
+    start_date timestamp without time zone NOT NULL DEFAULT now(),
+    end_date timestamp without time zone NULL,
+    closed BOOLEAN NOT NULL DEFAULT false,
+    champion_id INT NULL
+  );
+
+ALTER TABLE
+  public.season
+ADD
+  CONSTRAINT season_pkey PRIMARY KEY (id);
+
+ALTER TABLE
+  public.season
+ADD
+  CONSTRAINT fk_group_id FOREIGN KEY (group_id) REFERENCES grp(id);
+
+ALTER TABLE
+  public.season
+ADD
+  CONSTRAINT fk_champion_id FOREIGN KEY (champion_id) REFERENCES player(id);
+
+-- Only one season can be running in a group at a time
+CREATE UNIQUE INDEX season_one_open_per_group ON season (group_id) WHERE NOT closed;
+
+CREATE TABLE
+  public.season_standing (
+    season_id INT NOT NULL,
+    player_id INT NOT NULL,
+    position INT NOT NULL,
+    wins INT NOT NULL,
+    points INT NOT NULL,
+    games INT NOT NULL,
+    rating DOUBLE PRECISION NOT NULL
+  );
+
+ALTER TABLE
+  public.season_standing
+ADD
+  CONSTRAINT season_standing_pkey PRIMARY KEY (season_id, player_id);
+
+ALTER TABLE
+  public.season_standing
+ADD
+  CONSTRAINT fk_season_id FOREIGN KEY (season_id) REFERENCES season(id);
+
+ALTER TABLE
+  public.season_standing
+ADD
+  CONSTRAINT fk_player_id FOREIGN KEY (player_id) REFERENCES player(id);
@@ -0,0 +1 @@
+DROP TABLE public.badge_tier;
@@ -0,0 +1,42 @@
+CREATE TABLE
+  public.badge_tier (
+    id serial NOT NULL,
+    group_id INT NOT NULL,
+    name text NOT NULL,
+    emoji text NOT NULL,
+    -- Exactly one of these is set
+    score INT NULL,
+    fraction REAL NULL
+  );
+
+ALTER TABLE
+  public.badge_tier
+ADD
+  CONSTRAINT badge_tier_pkey PRIMARY KEY (id);
+
+ALTER TABLE
+  public.badge_tier
+ADD
+  CONSTRAINT fk_group_id FOREIGN KEY (group_id) REFERENCES grp(id);
+
+ALTER TABLE
+  public.badge_tier
+ADD
+  CONSTRAINT badge_tier_name_unique UNIQUE (group_id, name);
+
+ALTER TABLE
+  public.badge_tier
+ADD
+  CONSTRAINT badge_tier_one_threshold CHECK ((score IS NULL) <> (fraction IS NULL));
+
+-- Tiers that used to be hardcoded
+INSERT INTO badge_tier (group_id, name, emoji, fraction)
+SELECT grp.id, tier.name, tier.emoji, tier.fraction
+FROM grp
+CROSS JOIN (
+  VALUES
+    ('star', '🎖️', 1.0),
+    ('gold', '🥇', 0.94),
+    ('silver', '🥈', 0.88),
+    ('bronze', '🥉', 0.83)
+) AS tier (name, emoji, fraction);
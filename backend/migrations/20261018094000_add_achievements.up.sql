@@ -0,0 +1,35 @@
+CREATE TABLE
+  public.player_achievement (
+    id serial NOT NULL,
+    player_id INT NOT NULL,
+    group_id INT NOT NULL,
+    achievement text NOT NULL,
+    game_id INT NULL,
+    unlocked_at timestamp without time zone NOT NULL DEFAULT now()
+  );
+
+ALTER TABLE
+  public.player_achievement
+ADD
+  CONSTRAINT player_achievement_pkey PRIMARY KEY (id);
+
+ALTER TABLE
+  public.player_achievement
+ADD
+  CONSTRAINT fk_player_id FOREIGN KEY (player_id) REFERENCES player(id);
+
+ALTER TABLE
+  public.player_achievement
+ADD
+  CONSTRAINT fk_group_id FOREIGN KEY (group_id) REFERENCES grp(id);
+
+-- Keep the achievement if the game that unlocked it is deleted
+ALTER TABLE
+  public.player_achievement
+ADD
+  CONSTRAINT fk_game_id FOREIGN KEY (game_id) REFERENCES game(id) ON DELETE SET NULL;
+
+ALTER TABLE
+  public.player_achievement
+ADD
+  CONSTRAINT player_achievement_unique UNIQUE (player_id, group_id, achievement);
@@ -0,0 +1,5 @@
+ALTER TABLE admin_session
+DROP created_at,
+DROP last_refreshed_at,
+DROP user_agent,
+DROP ip;
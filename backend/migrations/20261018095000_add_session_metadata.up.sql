@@ -0,0 +1,5 @@
+ALTER TABLE admin_session
+ADD created_at timestamp without time zone NOT NULL DEFAULT now(),
+ADD last_refreshed_at timestamp without time zone NOT NULL DEFAULT now(),
+ADD user_agent text NULL,
+ADD ip text NULL;
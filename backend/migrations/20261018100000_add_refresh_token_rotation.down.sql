@@ -0,0 +1,2 @@
+ALTER TABLE admin_session
+DROP refresh_token_id;
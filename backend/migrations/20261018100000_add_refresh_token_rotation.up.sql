@@ -0,0 +1,3 @@
+-- ID of the only refresh token that is currently valid for the session
+ALTER TABLE admin_session
+ADD refresh_token_id UUID NULL;
@@ -0,0 +1,6 @@
+DROP TABLE public.admin_recovery_code;
+
+ALTER TABLE admin_user
+DROP totp_secret,
+DROP totp_enabled,
+DROP totp_last_step;
@@ -0,0 +1,24 @@
+-- The secret is stored as soon as enrolment starts, but isn't required to log in until the
+-- admin has confirmed it with a valid code
+ALTER TABLE admin_user
+ADD totp_secret BYTEA NULL,
+ADD totp_enabled BOOLEAN NOT NULL DEFAULT false,
+ADD totp_last_step BIGINT NULL;
+
+CREATE TABLE
+  public.admin_recovery_code (
+    id serial NOT NULL,
+    user_id INT NOT NULL,
+    code_hash text NOT NULL,
+    used_at timestamp without time zone NULL
+  );
+
+ALTER TABLE
+  public.admin_recovery_code
+ADD
+  CONSTRAINT admin_recovery_code_pkey PRIMARY KEY (id);
+
+ALTER TABLE
+  public.admin_recovery_code
+ADD
+  CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES admin_user(id) ON DELETE CASCADE;
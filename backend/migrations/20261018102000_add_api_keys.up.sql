@@ -0,0 +1,28 @@
+CREATE TABLE
+  public.api_key (
+    id serial NOT NULL,
+    name text NOT NULL,
+    key_hash text NOT NULL,
+    scopes text[] NOT NULL,
+    -- NULL if the key can be used for any group
+    group_ids INT[] NULL,
+    created_by INT NULL,
+    created_at timestamp without time zone NOT NULL DEFAULT now(),
+    last_used_at timestamp without time zone NULL
+  );
+
+ALTER TABLE
+  public.api_key
+ADD
+  CONSTRAINT api_key_pkey PRIMARY KEY (id);
+
+ALTER TABLE
+  public.api_key
+ADD
+  CONSTRAINT api_key_hash_unique UNIQUE (key_hash);
+
+-- Keep keys working if the admin that created them is deleted
+ALTER TABLE
+  public.api_key
+ADD
+  CONSTRAINT fk_created_by FOREIGN KEY (created_by) REFERENCES admin_user(id) ON DELETE SET NULL;
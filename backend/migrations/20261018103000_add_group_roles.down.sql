@@ -0,0 +1,4 @@
+ALTER TABLE grp
+DROP private;
+
+DROP TABLE public.group_role;
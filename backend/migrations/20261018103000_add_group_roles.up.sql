@@ -0,0 +1,41 @@
+CREATE TABLE
+  public.group_role (
+    user_id INT NOT NULL,
+    group_id INT NOT NULL,
+    role text NOT NULL
+  );
+
+ALTER TABLE
+  public.group_role
+ADD
+  CONSTRAINT group_role_pkey PRIMARY KEY (user_id, group_id);
+
+ALTER TABLE
+  public.group_role
+ADD
+  CONSTRAINT fk_user_id FOREIGN KEY (user_id) REFERENCES admin_user(id) ON DELETE CASCADE;
+
+ALTER TABLE
+  public.group_role
+ADD
+  CONSTRAINT fk_group_id FOREIGN KEY (group_id) REFERENCES grp(id);
+
+ALTER TABLE
+  public.group_role
+ADD
+  CONSTRAINT group_role_valid CHECK (role IN ('owner', 'scorekeeper', 'viewer'));
+
+-- Private groups can only be seen by admins with a role in them
+ALTER TABLE grp
+ADD private BOOLEAN NOT NULL DEFAULT false;
+
+-- Every admin could previously modify every group, so they keep that
+INSERT INTO
+  group_role (user_id, group_id, role)
+SELECT
+  admin_user.id,
+  grp.id,
+  'owner'
+FROM
+  admin_user
+  CROSS JOIN grp;
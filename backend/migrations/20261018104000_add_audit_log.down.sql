@@ -0,0 +1,4 @@
+-- Dropping the table skips the append-only triggers, which are dropped with it
+DROP TABLE public.audit_log;
+
+DROP FUNCTION audit_log_append_only();
@@ -0,0 +1,36 @@
+CREATE TABLE
+  public.audit_log (
+    id SERIAL NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    -- Not foreign keys, so entries are kept after the admin or key is deleted
+    admin_id INT NULL,
+    api_key_id INT NULL,
+    actor_name text NOT NULL,
+    action text NOT NULL,
+    group_id INT NULL,
+    target_id INT NULL,
+    before JSONB NULL,
+    after JSONB NULL
+  );
+
+ALTER TABLE
+  public.audit_log
+ADD
+  CONSTRAINT audit_log_pkey PRIMARY KEY (id);
+
+CREATE INDEX audit_log_group_id_idx ON audit_log (group_id, id);
+
+-- The log is append-only
+CREATE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_no_update
+BEFORE UPDATE OR DELETE ON audit_log
+FOR EACH ROW EXECUTE FUNCTION audit_log_append_only();
+
+CREATE TRIGGER audit_log_no_truncate
+BEFORE TRUNCATE ON audit_log
+FOR EACH STATEMENT EXECUTE FUNCTION audit_log_append_only();
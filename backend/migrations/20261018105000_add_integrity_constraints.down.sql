@@ -0,0 +1,17 @@
+DROP INDEX game_group_id_date_idx;
+
+DROP INDEX game_score_player_id_idx;
+
+DROP INDEX game_score_game_id_idx;
+
+ALTER TABLE public.player_group
+DROP CONSTRAINT player_group_unique,
+DROP CONSTRAINT fk_group_id,
+DROP CONSTRAINT fk_player_id;
+
+ALTER TABLE public.game
+DROP CONSTRAINT fk_group_id;
+
+ALTER TABLE public.game_score
+DROP CONSTRAINT fk_player_id,
+DROP CONSTRAINT fk_game_id;
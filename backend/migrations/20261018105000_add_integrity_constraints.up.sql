@@ -0,0 +1,39 @@
+-- Fails if there are orphaned rows or duplicate memberships. Run `tools check-integrity --repair`
+-- first to clean them up
+ALTER TABLE
+  public.game_score
+ADD
+  CONSTRAINT fk_game_id FOREIGN KEY (game_id) REFERENCES game(id) ON DELETE CASCADE;
+
+-- Scores are history, so players can't be deleted while they have any
+ALTER TABLE
+  public.game_score
+ADD
+  CONSTRAINT fk_player_id FOREIGN KEY (player_id) REFERENCES player(id);
+
+ALTER TABLE
+  public.game
+ADD
+  CONSTRAINT fk_group_id FOREIGN KEY (group_id) REFERENCES grp(id);
+
+ALTER TABLE
+  public.player_group
+ADD
+  CONSTRAINT fk_player_id FOREIGN KEY (player_id) REFERENCES player(id) ON DELETE CASCADE;
+
+ALTER TABLE
+  public.player_group
+ADD
+  CONSTRAINT fk_group_id FOREIGN KEY (group_id) REFERENCES grp(id) ON DELETE CASCADE;
+
+-- Group first, so it also serves looking up a group's members
+ALTER TABLE
+  public.player_group
+ADD
+  CONSTRAINT player_group_unique UNIQUE (group_id, player_id);
+
+CREATE INDEX game_score_game_id_idx ON game_score (game_id);
+
+CREATE INDEX game_score_player_id_idx ON game_score (player_id);
+
+CREATE INDEX game_group_id_date_idx ON game (group_id, date);
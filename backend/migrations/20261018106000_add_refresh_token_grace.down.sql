@@ -0,0 +1,3 @@
+ALTER TABLE admin_session
+DROP previous_refresh_token_id,
+DROP refresh_token_rotated_at;
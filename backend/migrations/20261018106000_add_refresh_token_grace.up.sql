@@ -0,0 +1,5 @@
+-- The refresh token that was replaced most recently, and when. It is still accepted for a short
+-- time, so requests that refreshed at the same time don't look like a stolen token
+ALTER TABLE admin_session
+ADD previous_refresh_token_id UUID NULL,
+ADD refresh_token_rotated_at timestamp without time zone NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE api_key
+ALTER COLUMN group_ids DROP NOT NULL;
@@ -0,0 +1,6 @@
+-- Keys that weren't restricted to groups could be used for every group, including ones created
+-- later. Restrict them to the groups that exist now
+UPDATE api_key SET group_ids = ARRAY(SELECT id FROM grp ORDER BY id) WHERE group_ids IS NULL;
+
+ALTER TABLE api_key
+ALTER COLUMN group_ids SET NOT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE admin_user
+DROP site_admin;
@@ -0,0 +1,17 @@
+-- Site admins can manage admin accounts. Only they or `tools` can make another site admin
+ALTER TABLE admin_user
+ADD site_admin BOOLEAN NOT NULL DEFAULT false;
+
+-- Owners could previously manage admins, so they keep that
+UPDATE admin_user
+SET
+  site_admin = true
+WHERE
+  id IN (
+    SELECT
+      user_id
+    FROM
+      group_role
+    WHERE
+      role = 'owner'
+  );
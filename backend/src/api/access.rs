@@ -0,0 +1,528 @@
+use std::{collections::HashMap, fmt, future::Future, pin::Pin, str::FromStr};
+
+use actix_web::{dev::Payload, http::header, web::Data, FromRequest, HttpRequest};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    api_keys::{find_key, is_api_key, ApiKey, Scope},
+    error::{Error, Result},
+    routes::auth::authorised_claims,
+    AppState,
+};
+
+/// What an admin can do in a group. Each role can do everything the ones before it can
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    /// Can see the group if it is private
+    Viewer,
+    /// Can add, edit and delete games, and manage the players in the group
+    Scorekeeper,
+    /// Can change the group's settings and who has roles in it
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Scorekeeper => "scorekeeper",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "scorekeeper" => Ok(Role::Scorekeeper),
+            "owner" => Ok(Role::Owner),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    ViewGroup,
+    EditGames,
+    EditPlayers,
+    ManageGroup,
+    /// Create a group. Needs the owner role in at least one group, or to be a site admin
+    CreateGroup,
+    /// List, create and delete admin accounts. Only site admins have this
+    ManageAdmins,
+}
+
+impl Permission {
+    /// Permission an admin needs to create an API key with the scope
+    pub fn for_scope(scope: Scope) -> Self {
+        match scope {
+            Scope::Read => Permission::ViewGroup,
+            Scope::GamesWrite => Permission::EditGames,
+            Scope::PlayersWrite => Permission::EditPlayers,
+        }
+    }
+
+    /// Minimum role an admin needs. No role is enough for permissions only site admins have
+    fn role(self) -> Option<Role> {
+        match self {
+            Permission::ViewGroup => Some(Role::Viewer),
+            Permission::EditGames | Permission::EditPlayers => Some(Role::Scorekeeper),
+            Permission::ManageGroup | Permission::CreateGroup => Some(Role::Owner),
+            Permission::ManageAdmins => None,
+        }
+    }
+
+    /// Whether site admins have the permission without a role
+    fn site_wide(self) -> bool {
+        matches!(self, Permission::CreateGroup | Permission::ManageAdmins)
+    }
+
+    /// Scope an API key needs. API keys can't manage groups or admins
+    fn scope(self) -> Option<Scope> {
+        match self {
+            Permission::ViewGroup => Some(Scope::Read),
+            Permission::EditGames => Some(Scope::GamesWrite),
+            Permission::EditPlayers => Some(Scope::PlayersWrite),
+            Permission::ManageGroup | Permission::CreateGroup | Permission::ManageAdmins => None,
+        }
+    }
+}
+
+fn unauthorised() -> Error {
+    Error::unauthorised("Not authorised to make this request")
+}
+
+/// Token from the `Authorization: Bearer ...` header
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    value.strip_prefix("Bearer ").map(|token| token.to_string())
+}
+
+/// An admin logged in with a valid access token. Rejects the request with 401 otherwise
+#[derive(Debug, Clone)]
+pub struct Admin {
+    pub id: i32,
+    pub username: String,
+    /// Session the access token belongs to
+    pub sid: Uuid,
+    /// Can manage admin accounts, which group roles can't give
+    pub site_admin: bool,
+}
+
+impl Admin {
+    async fn from_token(data: &AppState, token: &str) -> Result<Self> {
+        let claims = authorised_claims(data, token)
+            .await?
+            .ok_or_else(unauthorised)?;
+        let user = sqlx::query!(
+            "SELECT id, site_admin FROM admin_user WHERE username = $1",
+            claims.sub
+        )
+        .fetch_optional(data.pg_pool.as_ref())
+        .await?
+        .ok_or_else(unauthorised)?;
+
+        Ok(Admin {
+            id: user.id,
+            username: claims.sub,
+            sid: Uuid::parse_str(&claims.sid).map_err(|_| unauthorised())?,
+            site_admin: user.site_admin,
+        })
+    }
+
+    /// The admin's role in each group they have one in
+    pub async fn roles(&self, pool: &PgPool) -> Result<HashMap<i32, Role>> {
+        roles_of(pool, self.id).await
+    }
+
+    /// Whether the admin has the permission in all the groups
+    pub async fn has(
+        &self,
+        pool: &PgPool,
+        permission: Permission,
+        group_ids: &[i32],
+    ) -> Result<bool> {
+        Ok(admin_allows(
+            self,
+            &self.roles(pool).await?,
+            permission,
+            group_ids,
+        ))
+    }
+
+    /// Fails with 403 unless the admin has the permission in all the groups
+    pub async fn require(
+        &self,
+        pool: &PgPool,
+        permission: Permission,
+        group_ids: &[i32],
+    ) -> Result<()> {
+        if self.has(pool, permission, group_ids).await? {
+            Ok(())
+        } else {
+            Err(Error::Forbidden)
+        }
+    }
+}
+
+impl FromRequest for Admin {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let data = req.app_data::<Data<AppState>>().cloned();
+        let token = bearer_token(req);
+
+        Box::pin(async move {
+            let (Some(data), Some(token)) = (data, token) else {
+                return Err(unauthorised());
+            };
+
+            Admin::from_token(&data, &token).await
+        })
+    }
+}
+
+/// Whoever is making the request - either a logged in admin or an API key. Rejects the request
+/// with 401 if neither
+#[derive(Debug, Clone)]
+pub enum Caller {
+    Admin(Admin),
+    ApiKey(ApiKey),
+}
+
+/// Each group the admin has a role in, and the role
+async fn roles_of(pool: &PgPool, user_id: i32) -> Result<HashMap<i32, Role>> {
+    let roles = sqlx::query!(
+        "SELECT group_id, role FROM group_role WHERE user_id = $1",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .filter_map(|r| Some((r.group_id, r.role.parse().ok()?)))
+    .collect();
+
+    Ok(roles)
+}
+
+/// Whether the admin, with these roles, has the permission in all the groups. If no groups are
+/// given, they only need the role in one group
+fn admin_allows(
+    admin: &Admin,
+    roles: &HashMap<i32, Role>,
+    permission: Permission,
+    group_ids: &[i32],
+) -> bool {
+    (admin.site_admin && permission.site_wide()) || roles_allow(roles, permission, group_ids)
+}
+
+/// Whether these roles give the permission in all the groups, or in one group if none are given
+fn roles_allow(roles: &HashMap<i32, Role>, permission: Permission, group_ids: &[i32]) -> bool {
+    let Some(required) = permission.role() else {
+        return false;
+    };
+
+    if group_ids.is_empty() {
+        return roles.values().any(|role| *role >= required);
+    }
+
+    group_ids
+        .iter()
+        .all(|id| roles.get(id).is_some_and(|role| *role >= required))
+}
+
+/// Whether the caller has the permission in all the groups. For API keys, `roles` are those of the
+/// admin who created the key, which are checked again on every request in case they've changed
+fn allows(
+    caller: &Caller,
+    roles: &HashMap<i32, Role>,
+    permission: Permission,
+    group_ids: &[i32],
+) -> bool {
+    match caller {
+        Caller::ApiKey(key) => {
+            permission
+                .scope()
+                .is_some_and(|scope| key.allows(scope, group_ids))
+                && roles_allow(roles, permission, group_ids)
+        }
+        Caller::Admin(admin) => admin_allows(admin, roles, permission, group_ids),
+    }
+}
+
+impl Caller {
+    async fn from_token(data: &AppState, token: &str) -> Result<Self> {
+        if is_api_key(token) {
+            if !data.features.api_keys {
+                return Err(unauthorised());
+            }
+
+            return find_key(data.pg_pool.as_ref(), token)
+                .await?
+                .map(Caller::ApiKey)
+                .ok_or_else(unauthorised);
+        }
+
+        Admin::from_token(data, token).await.map(Caller::Admin)
+    }
+
+    async fn roles(&self, pool: &PgPool) -> Result<HashMap<i32, Role>> {
+        match self {
+            Caller::Admin(admin) => admin.roles(pool).await,
+            Caller::ApiKey(key) => roles_of(pool, key.created_by).await,
+        }
+    }
+
+    /// Fails with 403 unless the caller has the permission in all the groups
+    pub async fn require(
+        &self,
+        pool: &PgPool,
+        permission: Permission,
+        group_ids: &[i32],
+    ) -> Result<()> {
+        let roles = self.roles(pool).await?;
+        if allows(self, &roles, permission, group_ids) {
+            Ok(())
+        } else {
+            Err(Error::Forbidden)
+        }
+    }
+}
+
+impl FromRequest for Caller {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let data = req.app_data::<Data<AppState>>().cloned();
+        let token = bearer_token(req);
+
+        Box::pin(async move {
+            let (Some(data), Some(token)) = (data, token) else {
+                return Err(unauthorised());
+            };
+
+            Caller::from_token(&data, &token).await
+        })
+    }
+}
+
+/// Caller for endpoints that can be used without logging in. Requests without a token are
+/// allowed, but an invalid token is still rejected with 401 so the client knows to refresh it
+#[derive(Debug, Clone)]
+pub struct MaybeCaller(pub Option<Caller>);
+
+impl MaybeCaller {
+    /// Private groups this caller can't see
+    pub async fn hidden_groups(&self, pool: &PgPool) -> Result<Vec<i32>> {
+        let private = sqlx::query_scalar!("SELECT id FROM grp WHERE private")
+            .fetch_all(pool)
+            .await?;
+
+        let Some(caller) = &self.0 else {
+            return Ok(private);
+        };
+
+        let roles = caller.roles(pool).await?;
+        let hidden = private
+            .into_iter()
+            .filter(|id| !allows(caller, &roles, Permission::ViewGroup, &[*id]))
+            .collect();
+
+        Ok(hidden)
+    }
+
+    /// Fails with 404 if the group is private and the caller can't see it, so private groups
+    /// can't be discovered
+    pub async fn require_view(&self, pool: &PgPool, group_id: i32) -> Result<()> {
+        if self.hidden_groups(pool).await?.contains(&group_id) {
+            return Err(Error::not_found("Group not found"));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromRequest for MaybeCaller {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let data = req.app_data::<Data<AppState>>().cloned();
+        let token = bearer_token(req);
+
+        Box::pin(async move {
+            let (Some(data), Some(token)) = (data, token) else {
+                return Ok(MaybeCaller(None));
+            };
+
+            Caller::from_token(&data, &token)
+                .await
+                .map(|caller| MaybeCaller(Some(caller)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_keys::{generate_key, hash_key};
+
+    fn admin(site_admin: bool) -> Admin {
+        Admin {
+            id: 1,
+            username: "admin".to_string(),
+            sid: Uuid::nil(),
+            site_admin,
+        }
+    }
+
+    #[test]
+    fn only_site_admins_manage_admins() {
+        let owner = HashMap::from([(1, Role::Owner)]);
+        assert!(!admin_allows(
+            &admin(false),
+            &owner,
+            Permission::ManageAdmins,
+            &[]
+        ));
+        assert!(admin_allows(
+            &admin(true),
+            &HashMap::new(),
+            Permission::ManageAdmins,
+            &[]
+        ));
+    }
+
+    #[test]
+    fn creating_groups_needs_an_owner_role_or_site_admin() {
+        let viewer = HashMap::from([(1, Role::Viewer), (2, Role::Scorekeeper)]);
+        let owner = HashMap::from([(1, Role::Viewer), (2, Role::Owner)]);
+        assert!(!admin_allows(
+            &admin(false),
+            &HashMap::new(),
+            Permission::CreateGroup,
+            &[]
+        ));
+        assert!(!admin_allows(
+            &admin(false),
+            &viewer,
+            Permission::CreateGroup,
+            &[]
+        ));
+        assert!(admin_allows(
+            &admin(false),
+            &owner,
+            Permission::CreateGroup,
+            &[]
+        ));
+        assert!(admin_allows(
+            &admin(true),
+            &HashMap::new(),
+            Permission::CreateGroup,
+            &[]
+        ));
+    }
+
+    #[test]
+    fn site_admins_still_need_roles_in_groups() {
+        let viewer = HashMap::from([(1, Role::Viewer)]);
+        assert!(!admin_allows(
+            &admin(true),
+            &viewer,
+            Permission::EditGames,
+            &[1]
+        ));
+        assert!(!admin_allows(
+            &admin(true),
+            &HashMap::new(),
+            Permission::ViewGroup,
+            &[1]
+        ));
+    }
+
+    #[sqlx::test]
+    async fn api_keys_lose_access_with_their_creator(pool: PgPool) {
+        let admin_id = sqlx::query_scalar!(
+            "INSERT INTO admin_user (username, password_hash) VALUES ('test', '') RETURNING id"
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let group_id = sqlx::query_scalar!("INSERT INTO grp (name) VALUES ('test') RETURNING id")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        sqlx::query!(
+            "INSERT INTO group_role (user_id, group_id, role) VALUES ($1, $2, 'owner')",
+            admin_id,
+            group_id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let key = generate_key();
+        sqlx::query!(
+            "INSERT INTO api_key (name, key_hash, scopes, group_ids, created_by)
+            VALUES ('test', $1, $2, $3, $4)",
+            hash_key(&key),
+            &["games:write".to_string()],
+            &[group_id],
+            admin_id,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let can_edit_games = || async {
+            let Some(key) = find_key(&pool, &key).await.unwrap() else {
+                return false;
+            };
+            Caller::ApiKey(key)
+                .require(&pool, Permission::EditGames, &[group_id])
+                .await
+                .is_ok()
+        };
+        assert!(can_edit_games().await);
+
+        sqlx::query!(
+            "UPDATE group_role SET role = 'viewer' WHERE user_id = $1",
+            admin_id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert!(!can_edit_games().await);
+
+        sqlx::query!(
+            "UPDATE group_role SET role = 'scorekeeper' WHERE user_id = $1",
+            admin_id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert!(can_edit_games().await);
+
+        sqlx::query!("DELETE FROM admin_user WHERE id = $1", admin_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(find_key(&pool, &key).await.unwrap().is_none());
+        assert!(!can_edit_games().await);
+    }
+}
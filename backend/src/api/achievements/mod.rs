@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use sqlx::{Error, PgConnection};
+
+pub mod rules;
+
+/// A player's result in a single game
+pub struct GameResult {
+    pub game_id: i32,
+    pub score: i32,
+    pub won: bool,
+    pub last: bool,
+}
+
+/// Everything a rule can look at when a game is added
+pub struct AchievementContext {
+    /// The player's games in the group, oldest first, ending with the game being checked
+    pub history: Vec<GameResult>,
+    pub max_score: Option<i32>,
+}
+
+impl AchievementContext {
+    pub fn latest(&self) -> &GameResult {
+        self.history.last().unwrap()
+    }
+}
+
+/// An achievement that can be unlocked by a game. Add new ones to `RULES`.
+pub trait Rule: Sync {
+    /// Stored in the database, so must not change once released
+    fn id(&self) -> &'static str;
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn is_unlocked(&self, ctx: &AchievementContext) -> bool;
+}
+
+pub const RULES: &[&dyn Rule] = &[
+    &rules::FirstWin,
+    &rules::Centurion,
+    &rules::PerfectScore,
+    &rules::OnFire,
+    &rules::Comeback,
+];
+
+pub fn find_rule(id: &str) -> Option<&'static dyn Rule> {
+    RULES.iter().find(|r| r.id() == id).copied()
+}
+
+async fn get_context(
+    conn: &mut PgConnection,
+    player_id: i32,
+    group_id: i32,
+) -> Result<AchievementContext, Error> {
+    let history = sqlx::query!(
+        r#"SELECT
+            game.id,
+            game_score.score,
+            MAX(others.score) as "max!",
+            MIN(others.score) as "min!",
+            COUNT(others.id) as "players!"
+        FROM game_score
+        INNER JOIN game ON game.id = game_score.game_id
+        INNER JOIN game_score others ON others.game_id = game.id
+        WHERE game_score.player_id = $1 AND game.group_id = $2
+        GROUP BY game.id, game_score.score
+        ORDER BY game.date ASC, game.id ASC"#,
+        player_id,
+        group_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|g| GameResult {
+        game_id: g.id,
+        score: g.score,
+        won: g.score == g.max,
+        last: g.players > 1 && g.score == g.min && g.min != g.max,
+    })
+    .collect();
+
+    let max_score = sqlx::query_scalar!("SELECT max_score FROM grp WHERE id = $1", group_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    Ok(AchievementContext { history, max_score })
+}
+
+/// Checks every rule for each player in the game, and records any newly unlocked achievements
+pub async fn evaluate_game(
+    conn: &mut PgConnection,
+    group_id: i32,
+    game_id: i32,
+) -> Result<(), Error> {
+    let player_ids = sqlx::query_scalar!(
+        "SELECT player_id FROM game_score WHERE game_id = $1",
+        game_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for player_id in player_ids {
+        let ctx = get_context(conn, player_id, group_id).await?;
+        if ctx.history.last().map(|g| g.game_id) != Some(game_id) {
+            continue;
+        }
+
+        for rule in RULES.iter().filter(|r| r.is_unlocked(&ctx)) {
+            sqlx::query!(
+                "INSERT INTO player_achievement (player_id, group_id, achievement, game_id)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT DO NOTHING",
+                player_id,
+                group_id,
+                rule.id(),
+                game_id,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replays each player's games in the group and makes their unlocks match, for when a game is
+/// changed or deleted. Achievements the games no longer earn are taken away, and the rest are
+/// moved to the game that now unlocks them. Unlocks of rules that have since been removed are kept
+pub async fn reevaluate_players(
+    conn: &mut PgConnection,
+    group_id: i32,
+    player_ids: &[i32],
+) -> Result<(), Error> {
+    let rule_ids: Vec<&str> = RULES.iter().map(|r| r.id()).collect();
+
+    for &player_id in player_ids {
+        let AchievementContext { history, max_score } =
+            get_context(conn, player_id, group_id).await?;
+
+        // The first game each achievement is unlocked by
+        let mut ctx = AchievementContext {
+            history: Vec::with_capacity(history.len()),
+            max_score,
+        };
+        let mut unlocked = HashMap::new();
+        for game in history {
+            let game_id = game.game_id;
+            ctx.history.push(game);
+            for rule in RULES.iter().filter(|r| r.is_unlocked(&ctx)) {
+                unlocked.entry(rule.id()).or_insert(game_id);
+            }
+        }
+
+        let keep: Vec<&str> = unlocked.keys().copied().collect();
+        sqlx::query!(
+            "DELETE FROM player_achievement
+            WHERE player_id = $1 AND group_id = $2
+                AND achievement = ANY($3) AND NOT achievement = ANY($4)",
+            player_id,
+            group_id,
+            &rule_ids as &[&str],
+            &keep as &[&str],
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        for (achievement, game_id) in unlocked {
+            sqlx::query!(
+                "INSERT INTO player_achievement (player_id, group_id, achievement, game_id)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (player_id, group_id, achievement)
+                DO UPDATE SET game_id = EXCLUDED.game_id",
+                player_id,
+                group_id,
+                achievement,
+                game_id,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    async fn unlocks(pool: &PgPool, player_id: i32) -> Vec<(String, Option<i32>)> {
+        sqlx::query!(
+            "SELECT achievement, game_id FROM player_achievement
+            WHERE player_id = $1 ORDER BY achievement",
+            player_id
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|a| (a.achievement, a.game_id))
+        .collect()
+    }
+
+    async fn set_scores(pool: &PgPool, game_id: i32, scores: &[(i32, i32)]) {
+        sqlx::query!("DELETE FROM game_score WHERE game_id = $1", game_id)
+            .execute(pool)
+            .await
+            .unwrap();
+        for (player_id, score) in scores {
+            sqlx::query!(
+                "INSERT INTO game_score (score, game_id, player_id) VALUES ($1, $2, $3)",
+                score,
+                game_id,
+                player_id
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+        }
+    }
+
+    #[sqlx::test]
+    async fn corrected_games_change_achievements(pool: PgPool) {
+        let group_id = sqlx::query_scalar!(
+            "INSERT INTO grp (name, max_score) VALUES ('test', 60) RETURNING id"
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let mario = sqlx::query_scalar!("INSERT INTO player (name) VALUES ('Mario') RETURNING id")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        let luigi = sqlx::query_scalar!("INSERT INTO player (name) VALUES ('Luigi') RETURNING id")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        let mut game_ids = Vec::new();
+        for _ in 0..2 {
+            let game_id = sqlx::query_scalar!(
+                "INSERT INTO game (group_id) VALUES ($1) RETURNING id",
+                group_id
+            )
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            game_ids.push(game_id);
+        }
+
+        let mut conn = pool.acquire().await.unwrap();
+        set_scores(&pool, game_ids[0], &[(mario, 60), (luigi, 40)]).await;
+        evaluate_game(&mut conn, group_id, game_ids[0])
+            .await
+            .unwrap();
+        set_scores(&pool, game_ids[1], &[(mario, 30), (luigi, 50)]).await;
+        evaluate_game(&mut conn, group_id, game_ids[1])
+            .await
+            .unwrap();
+
+        let first_win = |game_id| ("first_win".to_string(), Some(game_id));
+        let perfect_score = |game_id| ("perfect_score".to_string(), Some(game_id));
+        assert_eq!(
+            unlocks(&pool, mario).await,
+            [first_win(game_ids[0]), perfect_score(game_ids[0])]
+        );
+        assert_eq!(
+            unlocks(&pool, luigi).await,
+            [
+                ("comeback".to_string(), Some(game_ids[1])),
+                first_win(game_ids[1])
+            ]
+        );
+
+        // The first game was a typo, Luigi won it
+        set_scores(&pool, game_ids[0], &[(mario, 40), (luigi, 60)]).await;
+        reevaluate_players(&mut conn, group_id, &[mario, luigi])
+            .await
+            .unwrap();
+        assert_eq!(unlocks(&pool, mario).await, []);
+        assert_eq!(
+            unlocks(&pool, luigi).await,
+            [first_win(game_ids[0]), perfect_score(game_ids[0])]
+        );
+
+        sqlx::query!("DELETE FROM game WHERE id = $1", game_ids[0])
+            .execute(&pool)
+            .await
+            .unwrap();
+        reevaluate_players(&mut conn, group_id, &[mario, luigi])
+            .await
+            .unwrap();
+        assert_eq!(unlocks(&pool, mario).await, []);
+        assert_eq!(unlocks(&pool, luigi).await, [first_win(game_ids[1])]);
+    }
+}
@@ -0,0 +1,104 @@
+use super::{AchievementContext, Rule};
+
+pub struct FirstWin;
+
+impl Rule for FirstWin {
+    fn id(&self) -> &'static str {
+        "first_win"
+    }
+
+    fn name(&self) -> &'static str {
+        "First Win"
+    }
+
+    fn description(&self) -> &'static str {
+        "Win a game"
+    }
+
+    fn is_unlocked(&self, ctx: &AchievementContext) -> bool {
+        ctx.latest().won
+    }
+}
+
+pub struct Centurion;
+
+impl Rule for Centurion {
+    fn id(&self) -> &'static str {
+        "games_played_100"
+    }
+
+    fn name(&self) -> &'static str {
+        "Centurion"
+    }
+
+    fn description(&self) -> &'static str {
+        "Play 100 games"
+    }
+
+    fn is_unlocked(&self, ctx: &AchievementContext) -> bool {
+        ctx.history.len() >= 100
+    }
+}
+
+pub struct PerfectScore;
+
+impl Rule for PerfectScore {
+    fn id(&self) -> &'static str {
+        "perfect_score"
+    }
+
+    fn name(&self) -> &'static str {
+        "Perfect Score"
+    }
+
+    fn description(&self) -> &'static str {
+        "Get the group's max score in a game"
+    }
+
+    fn is_unlocked(&self, ctx: &AchievementContext) -> bool {
+        ctx.max_score == Some(ctx.latest().score)
+    }
+}
+
+pub struct OnFire;
+
+impl Rule for OnFire {
+    fn id(&self) -> &'static str {
+        "win_streak_5"
+    }
+
+    fn name(&self) -> &'static str {
+        "On Fire"
+    }
+
+    fn description(&self) -> &'static str {
+        "Win 5 games in a row"
+    }
+
+    fn is_unlocked(&self, ctx: &AchievementContext) -> bool {
+        ctx.history.len() >= 5 && ctx.history.iter().rev().take(5).all(|g| g.won)
+    }
+}
+
+pub struct Comeback;
+
+impl Rule for Comeback {
+    fn id(&self) -> &'static str {
+        "comeback"
+    }
+
+    fn name(&self) -> &'static str {
+        "Comeback"
+    }
+
+    fn description(&self) -> &'static str {
+        "Win a game right after coming last"
+    }
+
+    fn is_unlocked(&self, ctx: &AchievementContext) -> bool {
+        match ctx.history.as_slice() {
+            [.., previous, latest] => previous.last && latest.won,
+            _ => false,
+        }
+    }
+}
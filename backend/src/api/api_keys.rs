@@ -0,0 +1,113 @@
+use std::{fmt, str::FromStr};
+
+use data_encoding::HEXLOWER;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+/// Prefix of every API key, so they can be told apart from JWTs
+const KEY_PREFIX: &str = "mks_";
+
+const KEY_SIZE: usize = 32;
+
+/// What an API key is allowed to do
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    #[serde(rename = "read")]
+    Read,
+    #[serde(rename = "games:write")]
+    GamesWrite,
+    #[serde(rename = "players:write")]
+    PlayersWrite,
+}
+
+impl Scope {
+    pub const ALL: &[Scope] = &[Scope::Read, Scope::GamesWrite, Scope::PlayersWrite];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::GamesWrite => "games:write",
+            Scope::PlayersWrite => "players:write",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .iter()
+            .find(|scope| scope.as_str() == s)
+            .copied()
+            .ok_or(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Groups the key can be used for
+    pub group_ids: Vec<i32>,
+    /// Admin that created the key. It can never do more than their roles allow
+    pub created_by: i32,
+}
+
+impl ApiKey {
+    /// Whether the key has the scope, and can be used for all the groups the request affects
+    pub fn allows(&self, scope: Scope, group_ids: &[i32]) -> bool {
+        let groups_allowed = group_ids.iter().all(|id| self.group_ids.contains(id));
+        self.scopes.contains(&scope) && groups_allowed
+    }
+}
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(KEY_PREFIX)
+}
+
+pub fn generate_key() -> String {
+    let mut key = vec![0; KEY_SIZE];
+    rand::thread_rng().fill_bytes(&mut key);
+    format!("{KEY_PREFIX}{}", HEXLOWER.encode(&key))
+}
+
+/// Keys are random enough that a fast hash is fine (unlike passwords)
+pub fn hash_key(key: &str) -> String {
+    HEXLOWER.encode(&Sha256::digest(key.as_bytes()))
+}
+
+/// Looks up the key, recording that it has been used. Keys whose creator has been deleted can't
+/// be used
+pub async fn find_key(pool: &PgPool, key: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+    let key = sqlx::query!(
+        r#"UPDATE api_key SET last_used_at = now()
+        WHERE key_hash = $1 AND created_by IS NOT NULL
+        RETURNING id, name, scopes, group_ids, created_by as "created_by!""#,
+        hash_key(key)
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(key) = key else {
+        return Ok(None);
+    };
+
+    Ok(Some(ApiKey {
+        id: key.id,
+        name: key.name,
+        // Ignore any scopes that no longer exist
+        scopes: key.scopes.iter().filter_map(|s| s.parse().ok()).collect(),
+        group_ids: key.group_ids,
+        created_by: key.created_by,
+    }))
+}
@@ -0,0 +1,165 @@
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgExecutor;
+
+use crate::access::{Admin, Caller};
+
+/// A change that is recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    CreateGame,
+    UpdateGame,
+    DeleteGame,
+    CreateGroup,
+    UpdateGroupSettings,
+    AddPlayerToGroup,
+    RemovePlayerFromGroup,
+    CreatePlayer,
+    UpdateBadgeTiers,
+    CreateSeason,
+    CloseSeason,
+    SetGroupRole,
+    RemoveGroupRole,
+    CreateAdmin,
+    DeleteAdmin,
+    ChangePassword,
+    RevokeSession,
+    CreateApiKey,
+    DeleteApiKey,
+    EnableTwoFactor,
+    DisableTwoFactor,
+}
+
+impl Action {
+    /// Actions whose target is an admin rather than a group, game etc.
+    pub const ON_ADMINS: &'static [Action] = &[
+        Action::CreateAdmin,
+        Action::DeleteAdmin,
+        Action::ChangePassword,
+        Action::RevokeSession,
+        Action::EnableTwoFactor,
+        Action::DisableTwoFactor,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::CreateGame => "game.create",
+            Action::UpdateGame => "game.update",
+            Action::DeleteGame => "game.delete",
+            Action::CreateGroup => "group.create",
+            Action::UpdateGroupSettings => "group.update_settings",
+            Action::AddPlayerToGroup => "group.add_player",
+            Action::RemovePlayerFromGroup => "group.remove_player",
+            Action::CreatePlayer => "player.create",
+            Action::UpdateBadgeTiers => "badge_tiers.update",
+            Action::CreateSeason => "season.create",
+            Action::CloseSeason => "season.close",
+            Action::SetGroupRole => "role.set",
+            Action::RemoveGroupRole => "role.remove",
+            Action::CreateAdmin => "admin.create",
+            Action::DeleteAdmin => "admin.delete",
+            Action::ChangePassword => "admin.change_password",
+            Action::RevokeSession => "admin.revoke_session",
+            Action::CreateApiKey => "api_key.create",
+            Action::DeleteApiKey => "api_key.delete",
+            Action::EnableTwoFactor => "two_factor.enable",
+            Action::DisableTwoFactor => "two_factor.disable",
+        }
+    }
+}
+
+/// Who made a change - either an admin or an API key
+#[derive(Debug, Clone)]
+pub struct Actor {
+    admin_id: Option<i32>,
+    api_key_id: Option<i32>,
+    name: String,
+}
+
+impl From<&Admin> for Actor {
+    fn from(admin: &Admin) -> Self {
+        Actor {
+            admin_id: Some(admin.id),
+            api_key_id: None,
+            name: admin.username.clone(),
+        }
+    }
+}
+
+impl From<&Caller> for Actor {
+    fn from(caller: &Caller) -> Self {
+        match caller {
+            Caller::Admin(admin) => admin.into(),
+            Caller::ApiKey(key) => Actor {
+                admin_id: None,
+                api_key_id: Some(key.id),
+                name: key.name.clone(),
+            },
+        }
+    }
+}
+
+/// What was changed. `before` and `after` are snapshots of the target, where it makes sense
+#[derive(Debug, Clone)]
+pub struct Entry {
+    action: Action,
+    group_id: Option<i32>,
+    target_id: Option<i32>,
+    before: Option<Value>,
+    after: Option<Value>,
+}
+
+impl Entry {
+    pub fn new(action: Action, target_id: impl Into<Option<i32>>) -> Self {
+        Entry {
+            action,
+            group_id: None,
+            target_id: target_id.into(),
+            before: None,
+            after: None,
+        }
+    }
+
+    /// Group the change was made in
+    pub fn group(mut self, group_id: i32) -> Self {
+        self.group_id = Some(group_id);
+        self
+    }
+
+    pub fn before(mut self, before: &impl Serialize) -> Self {
+        self.before = Some(serde_json::to_value(before).unwrap());
+        self
+    }
+
+    pub fn after(mut self, after: &impl Serialize) -> Self {
+        self.after = Some(serde_json::to_value(after).unwrap());
+        self
+    }
+}
+
+/// Adds an entry to the audit log. Pass the transaction making the change where there is one, so
+/// the entry is only kept if the change is
+pub async fn record<'e>(
+    executor: impl PgExecutor<'e>,
+    actor: impl Into<Actor>,
+    entry: Entry,
+) -> Result<(), sqlx::Error> {
+    let actor = actor.into();
+    sqlx::query!(
+        "INSERT INTO audit_log
+            (admin_id, api_key_id, actor_name, action, group_id, target_id, before, after)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        actor.admin_id,
+        actor.api_key_id,
+        actor.name,
+        entry.action.as_str(),
+        entry.group_id,
+        entry.target_id,
+        entry.before,
+        entry.after,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
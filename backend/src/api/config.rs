@@ -0,0 +1,221 @@
+use std::{
+    env, fs, io,
+    net::{IpAddr, ToSocketAddrs},
+    path::Path,
+    str::FromStr,
+};
+
+use serde::Deserialize;
+
+const HTTP_METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"];
+
+/// Server settings, read from a TOML file (`CONFIG_FILE`, defaults to `config.toml`). Anything
+/// missing from the file uses the default, and can be overridden by an environment variable
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub cors: CorsConfig,
+    pub database: DatabaseConfig,
+    pub features: Features,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    /// Reverse proxies in front of the API. The client IP is only read from `X-Forwarded-For`
+    /// when the request comes from one of these
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: "0.0.0.0:8080".to_string(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins the frontend is served from, e.g. `https://mariokart.cc`
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            origins: vec![
+                "https://mariokart.cc".to_string(),
+                "http://localhost:5173".to_string(),
+            ],
+            methods: ["GET", "POST", "PUT", "DELETE"].map(String::from).to_vec(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub max_connections: u32,
+    /// Apply pending migrations before starting the server
+    pub migrate_on_startup: bool,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            max_connections: 5,
+            migrate_on_startup: false,
+        }
+    }
+}
+
+/// Optional parts of the API that can be turned off
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    pub achievements: bool,
+    pub api_keys: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            achievements: true,
+            api_keys: true,
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Parses the environment variable if it is set
+fn env_override<T: FromStr>(name: &str) -> io::Result<Option<T>> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| invalid(format!("Invalid value for {name}: {value}"))),
+        Err(_) => Ok(None),
+    }
+}
+
+fn env_list_override(name: &str) -> Option<Vec<String>> {
+    let value = env::var(name).ok()?;
+    Some(
+        value
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect(),
+    )
+}
+
+impl Config {
+    /// Loads the config file if there is one, then applies environment overrides and validates
+    /// the result
+    pub fn load() -> io::Result<Self> {
+        let path = env::var("CONFIG_FILE").unwrap_or("config.toml".to_string());
+        let mut config = Self::from_file(Path::new(&path))?;
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> io::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                log::info!("No config file at {}, using defaults", path.display());
+                return Ok(Config::default());
+            }
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("Couldn't read config from {}: {e}", path.display()),
+                ))
+            }
+        };
+
+        toml::from_str(&contents)
+            .map_err(|e| invalid(format!("Invalid config in {}: {e}", path.display())))
+    }
+
+    fn apply_env(&mut self) -> io::Result<()> {
+        if let Ok(bind_address) = env::var("BIND_ADDRESS") {
+            self.server.bind_address = bind_address;
+        }
+        if let Some(proxies) = env_list_override("TRUSTED_PROXIES") {
+            self.server.trusted_proxies = proxies
+                .iter()
+                .map(|proxy| {
+                    proxy
+                        .parse()
+                        .map_err(|_| invalid(format!("Invalid value for TRUSTED_PROXIES: {proxy}")))
+                })
+                .collect::<io::Result<_>>()?;
+        }
+        if let Some(origins) = env_list_override("CORS_ORIGINS") {
+            self.cors.origins = origins;
+        }
+        if let Some(methods) = env_list_override("CORS_METHODS") {
+            self.cors.methods = methods;
+        }
+        if let Some(max_connections) = env_override("DB_MAX_CONNECTIONS")? {
+            self.database.max_connections = max_connections;
+        }
+        if let Some(migrate_on_startup) = env_override("DB_MIGRATE_ON_STARTUP")? {
+            self.database.migrate_on_startup = migrate_on_startup;
+        }
+        if let Some(achievements) = env_override("FEATURE_ACHIEVEMENTS")? {
+            self.features.achievements = achievements;
+        }
+        if let Some(api_keys) = env_override("FEATURE_API_KEYS")? {
+            self.features.api_keys = api_keys;
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        let mut errors = Vec::new();
+
+        if self.server.bind_address.to_socket_addrs().is_err() {
+            errors.push(format!(
+                "server.bind_address must be a host and port, e.g. 0.0.0.0:8080 (got {})",
+                self.server.bind_address
+            ));
+        }
+
+        for origin in &self.cors.origins {
+            let has_scheme = origin.starts_with("http://") || origin.starts_with("https://");
+            if !has_scheme || origin.ends_with('/') {
+                errors.push(format!(
+                    "cors.origins must be like https://example.com, without a trailing slash (got {origin})"
+                ));
+            }
+        }
+
+        for method in &self.cors.methods {
+            if !HTTP_METHODS.contains(&method.as_str()) {
+                errors.push(format!("cors.methods has an invalid method: {method}"));
+            }
+        }
+
+        if self.database.max_connections == 0 {
+            errors.push("database.max_connections must be at least 1".to_string());
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(invalid(format!("Invalid config:\n{}", errors.join("\n")))),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::{fmt, time::Duration};
+
+use actix_web::{
+    http::{header, StatusCode},
+    HttpRequest, HttpResponse, ResponseError,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::validation::FieldError;
+
+/// Messages for constraint violations, by constraint name. Foreign keys are named after the
+/// column, so the same name is used on several tables
+const CONSTRAINT_MESSAGES: &[(&str, &str)] = &[
+    ("name_unique", "Name must be unique"),
+    ("username_unique", "Name must be unique"),
+    ("badge_tier_name_unique", "Tier names must be unique"),
+    ("player_group_unique", "Player is already in this group"),
+    (
+        "season_one_open_per_group",
+        "Group already has an open season",
+    ),
+    ("fk_group_id", "Group not found"),
+    ("fk_player_id", "Player not found"),
+    ("fk_game_id", "Game not found"),
+    ("fk_season_id", "Season not found"),
+    ("fk_user_id", "Admin not found"),
+];
+
+fn constraint_message(constraint: Option<&str>) -> Option<&'static str> {
+    CONSTRAINT_MESSAGES
+        .iter()
+        .find(|(name, _)| Some(*name) == constraint)
+        .map(|(_, message)| *message)
+}
+
+/// Error returned by handlers. Every error is returned as JSON with a machine-readable code
+#[derive(Debug)]
+pub enum Error {
+    /// Request couldn't be parsed
+    BadRequest(String),
+    /// Not logged in, or the token is invalid
+    Unauthorised(String),
+    /// Logged in, but without permission
+    Forbidden,
+    NotFound(String),
+    Conflict(String),
+    Validation(Vec<FieldError>),
+    TooManyRequests(Duration),
+    /// Unexpected database error. Details are logged rather than returned
+    Database(sqlx::Error),
+}
+
+pub type Result<T = HttpResponse, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub fn unauthorised(message: impl Into<String>) -> Self {
+        Error::Unauthorised(message.into())
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Error::NotFound(message.into())
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Error::Conflict(message.into())
+    }
+
+    pub fn validation(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Error::Validation(vec![FieldError::new(field, message)])
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Error::BadRequest(_) => "bad_request",
+            Error::Unauthorised(_) => "unauthorised",
+            Error::Forbidden => "forbidden",
+            Error::NotFound(_) => "not_found",
+            Error::Conflict(_) => "conflict",
+            Error::Validation(_) => "validation_failed",
+            Error::TooManyRequests(_) => "too_many_requests",
+            Error::Database(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadRequest(message)
+            | Error::Unauthorised(message)
+            | Error::NotFound(message)
+            | Error::Conflict(message) => f.write_str(message),
+            Error::Forbidden => f.write_str("You do not have permission to make this request"),
+            Error::Validation(_) => f.write_str("Request is invalid"),
+            Error::TooManyRequests(_) => {
+                f.write_str("Too many failed login attempts, try again later")
+            }
+            Error::Database(_) => f.write_str("Something went wrong"),
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::RowNotFound => Error::not_found("Not found"),
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                let message = constraint_message(db.constraint()).unwrap_or("Already exists");
+                Error::conflict(message)
+            }
+            sqlx::Error::Database(db) if db.is_foreign_key_violation() => {
+                let message = constraint_message(db.constraint()).unwrap_or("Not found");
+                Error::not_found(message)
+            }
+            _ => Error::Database(e),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorBody {
+    code: String,
+    message: String,
+    /// Only for validation errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<Vec<FieldError>>,
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Error::Unauthorised(_) => StatusCode::UNAUTHORIZED,
+            Error::Forbidden => StatusCode::FORBIDDEN,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let Error::Database(e) = self {
+            log::error!("Database error: {e}");
+        }
+
+        let mut resp = HttpResponse::build(self.status_code());
+        if let Error::TooManyRequests(retry_after) = self {
+            // Round up so clients don't retry too early
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            resp.insert_header((header::RETRY_AFTER, secs.to_string()));
+        }
+
+        let errors = match self {
+            Error::Validation(errors) => Some(errors.clone()),
+            _ => None,
+        };
+
+        resp.json(ErrorBody {
+            code: self.code().to_string(),
+            message: self.to_string(),
+            errors,
+        })
+    }
+}
+
+/// Error handler for the JSON, query and path extractors, so bad requests get a JSON body too
+pub fn bad_request(err: impl fmt::Display, _req: &HttpRequest) -> actix_web::Error {
+    Error::BadRequest(err.to_string()).into()
+}
+
+/// Fallback for requests that don't match a route
+pub async fn unknown_route() -> Result {
+    Err(Error::not_found("Not found"))
+}
@@ -0,0 +1,162 @@
+use std::{collections::HashMap, fmt, fs, io, path::Path};
+
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    errors::{ErrorKind, Result},
+    DecodingKey, EncodingKey, Header, TokenData, Validation,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::jwt::{KEY_EXTENSION, LEGACY_KID};
+
+/// Keys used to sign and verify JWTs.
+///
+/// All keys in the directory are accepted for verification, so tokens signed with an older key
+/// remain valid after rotating. New tokens are signed with the newest key (the greatest key ID).
+/// Tokens without a `kid` are verified with the legacy key, if there is one
+pub struct JwtKeys {
+    signing_kid: String,
+    signing_key: EncodingKey,
+    verifying_keys: HashMap<String, DecodingKey>,
+}
+
+impl fmt::Debug for JwtKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't print the secrets
+        f.debug_struct("JwtKeys")
+            .field("signing_kid", &self.signing_kid)
+            .field("verifying_kids", &self.verifying_keys.keys())
+            .finish()
+    }
+}
+
+impl JwtKeys {
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let entries = fs::read_dir(dir).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Couldn't read JWT keys from {}: {e}", dir.display()),
+            )
+        })?;
+
+        let mut secrets = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != KEY_EXTENSION) {
+                continue;
+            }
+
+            let Some(kid) = path.file_stem().and_then(|kid| kid.to_str()) else {
+                continue;
+            };
+
+            secrets.push((kid.to_string(), fs::read(&path)?));
+        }
+
+        let Some((signing_kid, signing_secret)) = secrets
+            .iter()
+            .filter(|(kid, _)| kid != LEGACY_KID)
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .cloned()
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No JWT keys found in {}", dir.display()),
+            ));
+        };
+
+        Ok(Self {
+            signing_kid,
+            signing_key: EncodingKey::from_secret(&signing_secret),
+            verifying_keys: secrets
+                .iter()
+                .map(|(kid, secret)| (kid.clone(), DecodingKey::from_secret(secret)))
+                .collect(),
+        })
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> String {
+        let header = Header {
+            kid: Some(self.signing_kid.clone()),
+            ..Default::default()
+        };
+
+        encode(&header, claims, &self.signing_key).unwrap()
+    }
+
+    /// Verifies the token with the key named in its `kid` header
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<TokenData<T>> {
+        let kid = decode_header(token)?
+            .kid
+            .unwrap_or_else(|| LEGACY_KID.to_string());
+        let key = self
+            .verifying_keys
+            .get(&kid)
+            .ok_or(ErrorKind::InvalidToken)?;
+
+        decode(token, key, &Validation::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::{Duration, Utc};
+    use serde::Deserialize;
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Claims {
+        exp: i64,
+    }
+
+    fn claims() -> Claims {
+        Claims {
+            exp: (Utc::now() + Duration::minutes(5)).timestamp(),
+        }
+    }
+
+    fn key_dir(keys: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jwt_keys_{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        for (kid, secret) in keys {
+            fs::write(dir.join(format!("{kid}.{KEY_EXTENSION}")), secret).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn signs_with_the_newest_key() {
+        let dir = key_dir(&[("1", b"old"), ("2", b"new"), (LEGACY_KID, b"legacy")]);
+        let keys = JwtKeys::load(&dir).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        let token = keys.encode(&claims());
+        assert_eq!(decode_header(&token).unwrap().kid.as_deref(), Some("2"));
+        assert!(keys.decode::<Claims>(&token).is_ok());
+    }
+
+    #[test]
+    fn verifies_tokens_without_a_kid_with_the_legacy_key() {
+        let legacy_token = encode(
+            &Header::default(),
+            &claims(),
+            &EncodingKey::from_secret(b"legacy"),
+        )
+        .unwrap();
+
+        let dir = key_dir(&[("1", b"new"), (LEGACY_KID, b"legacy")]);
+        let keys = JwtKeys::load(&dir).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        assert!(keys.decode::<Claims>(&legacy_token).is_ok());
+
+        let dir = key_dir(&[("1", b"legacy")]);
+        let keys = JwtKeys::load(&dir).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        assert!(keys.decode::<Claims>(&legacy_token).is_err());
+    }
+}
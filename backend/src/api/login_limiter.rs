@@ -0,0 +1,254 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Failed attempts allowed for a username before it gets locked out
+const MAX_USERNAME_FAILURES: u32 = 5;
+
+/// Failed attempts allowed from an IP before it gets locked out. Higher than the username limit
+/// as several people can share an IP
+const MAX_IP_FAILURES: u32 = 20;
+
+/// Lockout after the first failure over the limit. Doubles with every failure after that
+const BASE_LOCKOUT: Duration = Duration::from_secs(30);
+
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Failures are forgotten once there haven't been any for this long
+const FAILURE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Most usernames or IPs to track failures for, so guessing lots of usernames can't use up memory
+const MAX_TRACKED: usize = 10_000;
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last_failed_at: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Tracks failed logins per username and per IP to slow down password guessing
+#[derive(Debug, Default)]
+pub struct LoginLimiter {
+    usernames: Mutex<HashMap<String, Failures>>,
+    ips: Mutex<HashMap<String, Failures>>,
+}
+
+/// Time left until the key is unlocked, if it is locked
+fn lockout_remaining(
+    failures: &HashMap<String, Failures>,
+    key: &str,
+    now: Instant,
+) -> Option<Duration> {
+    let locked_until = failures.get(key)?.locked_until?;
+    let remaining = locked_until.saturating_duration_since(now);
+    (!remaining.is_zero()).then_some(remaining)
+}
+
+fn record_failure(
+    failures: &mut HashMap<String, Failures>,
+    key: &str,
+    max_failures: u32,
+    now: Instant,
+) {
+    // Stop old failures building up
+    failures.retain(|_, f| now.saturating_duration_since(f.last_failed_at) < FAILURE_TTL);
+
+    if failures.len() >= MAX_TRACKED && !failures.contains_key(key) {
+        // Forget the oldest failure, preferring ones that aren't locked out so flooding with new
+        // keys can't unlock them
+        let oldest = failures
+            .iter()
+            .min_by_key(|(_, f)| (f.locked_until.is_some_and(|t| t > now), f.last_failed_at))
+            .map(|(key, _)| key.clone());
+
+        if let Some(oldest) = oldest {
+            failures.remove(&oldest);
+        }
+    }
+
+    let entry = failures.entry(key.to_string()).or_insert(Failures {
+        count: 0,
+        last_failed_at: now,
+        locked_until: None,
+    });
+
+    entry.count += 1;
+    entry.last_failed_at = now;
+
+    if entry.count > max_failures {
+        let doublings = (entry.count - max_failures - 1).min(31);
+        let lockout = BASE_LOCKOUT.saturating_mul(1 << doublings).min(MAX_LOCKOUT);
+        entry.locked_until = Some(now + lockout);
+    }
+}
+
+impl LoginLimiter {
+    /// How long until another attempt can be made, if the username or IP is locked out
+    pub fn retry_after(&self, username: &str, ip: Option<&str>) -> Option<Duration> {
+        self.retry_after_at(username, ip, Instant::now())
+    }
+
+    fn retry_after_at(&self, username: &str, ip: Option<&str>, now: Instant) -> Option<Duration> {
+        let username_remaining = lockout_remaining(&self.usernames.lock().unwrap(), username, now);
+        let ip_remaining = ip.and_then(|ip| lockout_remaining(&self.ips.lock().unwrap(), ip, now));
+
+        username_remaining.max(ip_remaining)
+    }
+
+    pub fn record_failure(&self, username: &str, ip: Option<&str>) {
+        self.record_failure_at(username, ip, Instant::now());
+    }
+
+    fn record_failure_at(&self, username: &str, ip: Option<&str>, now: Instant) {
+        record_failure(
+            &mut self.usernames.lock().unwrap(),
+            username,
+            MAX_USERNAME_FAILURES,
+            now,
+        );
+
+        if let Some(ip) = ip {
+            record_failure(&mut self.ips.lock().unwrap(), ip, MAX_IP_FAILURES, now);
+        }
+    }
+
+    /// Clears the failures for the username. IP failures are left to expire, otherwise logging in
+    /// to one account would allow guessing passwords for others
+    pub fn record_success(&self, username: &str) {
+        self.usernames.lock().unwrap().remove(username);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IP: Option<&str> = Some("192.0.2.1");
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn locks_a_username_after_five_failures() {
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+        for _ in 0..MAX_USERNAME_FAILURES {
+            limiter.record_failure_at("mario", IP, now);
+        }
+        assert_eq!(limiter.retry_after_at("mario", IP, now), None);
+
+        limiter.record_failure_at("mario", IP, now);
+        assert_eq!(limiter.retry_after_at("mario", IP, now), Some(BASE_LOCKOUT));
+        assert_eq!(
+            limiter.retry_after_at("mario", None, now),
+            Some(BASE_LOCKOUT)
+        );
+        assert_eq!(limiter.retry_after_at("luigi", IP, now), None);
+
+        assert_eq!(
+            limiter.retry_after_at("mario", IP, now + secs(10)),
+            Some(secs(20))
+        );
+        assert_eq!(
+            limiter.retry_after_at("mario", IP, now + BASE_LOCKOUT),
+            None
+        );
+    }
+
+    #[test]
+    fn locks_an_ip_after_twenty_failures() {
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+        for i in 0..MAX_IP_FAILURES {
+            limiter.record_failure_at(&format!("player{i}"), IP, now);
+        }
+        assert_eq!(limiter.retry_after_at("peach", IP, now), None);
+
+        limiter.record_failure_at("toad", IP, now);
+        assert_eq!(limiter.retry_after_at("peach", IP, now), Some(BASE_LOCKOUT));
+        assert_eq!(
+            limiter.retry_after_at("peach", Some("192.0.2.2"), now),
+            None
+        );
+        assert_eq!(limiter.retry_after_at("peach", None, now), None);
+    }
+
+    #[test]
+    fn lockout_doubles_up_to_an_hour() {
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+        for _ in 0..MAX_USERNAME_FAILURES {
+            limiter.record_failure_at("mario", None, now);
+        }
+
+        let mut expected = BASE_LOCKOUT;
+        for _ in 0..10 {
+            limiter.record_failure_at("mario", None, now);
+            assert_eq!(limiter.retry_after_at("mario", None, now), Some(expected));
+            expected = (expected * 2).min(MAX_LOCKOUT);
+        }
+        assert_eq!(
+            limiter.retry_after_at("mario", None, now),
+            Some(MAX_LOCKOUT)
+        );
+    }
+
+    #[test]
+    fn success_only_clears_the_username() {
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+        for _ in 0..MAX_IP_FAILURES + 1 {
+            limiter.record_failure_at("mario", IP, now);
+        }
+
+        limiter.record_success("mario");
+        assert_eq!(limiter.retry_after_at("mario", None, now), None);
+        assert!(limiter.retry_after_at("mario", IP, now).is_some());
+
+        // Starts counting from zero again
+        for _ in 0..MAX_USERNAME_FAILURES {
+            limiter.record_failure_at("mario", None, now);
+        }
+        assert_eq!(limiter.retry_after_at("mario", None, now), None);
+    }
+
+    #[test]
+    fn forgets_old_failures() {
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+        for _ in 0..MAX_USERNAME_FAILURES {
+            limiter.record_failure_at("mario", None, now);
+        }
+
+        limiter.record_failure_at("mario", None, now + FAILURE_TTL);
+        assert_eq!(
+            limiter.retry_after_at("mario", None, now + FAILURE_TTL),
+            None
+        );
+    }
+
+    #[test]
+    fn evicts_the_oldest_unlocked_key_when_full() {
+        let mut failures = HashMap::new();
+        let now = Instant::now();
+        for _ in 0..=MAX_USERNAME_FAILURES {
+            record_failure(&mut failures, "locked", MAX_USERNAME_FAILURES, now);
+        }
+        record_failure(&mut failures, "oldest", MAX_USERNAME_FAILURES, now);
+        for i in 2..MAX_TRACKED {
+            let later = now + secs(1);
+            record_failure(&mut failures, &i.to_string(), MAX_USERNAME_FAILURES, later);
+        }
+        assert_eq!(failures.len(), MAX_TRACKED);
+
+        record_failure(&mut failures, "new", MAX_USERNAME_FAILURES, now + secs(2));
+        assert_eq!(failures.len(), MAX_TRACKED);
+        assert!(failures.contains_key("new"));
+        assert!(!failures.contains_key("oldest"));
+        assert!(lockout_remaining(&failures, "locked", now + secs(2)).is_some());
+    }
+}
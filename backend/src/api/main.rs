@@ -1,64 +1,161 @@
-use std::{env, sync::Arc};
+use std::{env, io, net::IpAddr, path::Path, sync::Arc, time::Duration};
 
 use actix_cors::Cors;
-use actix_web::{http, web::Data, App, HttpServer};
-use routes::auth::{delete_session, login, refresh_auth_token};
-use routes::games::{add_game, get_previous_players};
+use actix_web::{
+    http, rt,
+    web::{self, Data, JsonConfig, PathConfig, QueryConfig},
+    App, HttpServer,
+};
+use backend::{badges, jwt, passwords, ratings, utils};
+use config::{Config, Features};
+use jwt_keys::JwtKeys;
+use login_limiter::LoginLimiter;
+use routes::achievements::{group_achievements, list_achievements, player_achievements};
+use routes::admins::{change_password, create_admin, delete_admin, list_admins};
+use routes::api_keys::{create_api_key, delete_api_key, list_api_keys};
+use routes::audit::list_audit_log;
+use routes::auth::{
+    delete_session, list_sessions, login, purge_expired_sessions, refresh_auth_token,
+    revoke_session,
+};
+use routes::badges::{get_badge_tiers, get_group_badges, update_badge_tiers};
+use routes::games::{add_game, delete_game, get_game, get_previous_players, update_game};
 use routes::groups::{
-    add_player_to_group, create_group, get_group, get_group_badges, get_group_stats, head_to_head,
-    list_groups, list_players, remove_player_from_group,
+    add_player_to_group, create_group, get_group, get_group_stats, head_to_head, list_games,
+    list_groups, list_players, remove_player_from_group, update_group_settings,
 };
 use routes::players::{
     create_player, list_all_players, player_best_streak, player_history, player_name,
+    player_rating_history,
 };
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use routes::records::group_records;
+use routes::roles::{list_group_roles, remove_group_role, set_group_role};
+use routes::seasons::{close_season, create_season, get_season, list_seasons};
+use routes::two_factor::{
+    confirm_two_factor_enrolment, disable_two_factor, start_two_factor_enrolment, verify_two_factor,
+};
+use session_cache::SessionCache;
+use sqlx::{migrate::Migrator, postgres::PgPoolOptions, Pool, Postgres};
 
+mod access;
+mod achievements;
+mod api_keys;
+mod audit;
+mod config;
+mod error;
+mod jwt_keys;
+mod login_limiter;
 mod routes;
-mod utils;
+mod session_cache;
+mod two_factor;
+mod validation;
+
+const SESSION_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Migrations in `migrations/`, embedded at compile time
+static MIGRATOR: Migrator = sqlx::migrate!();
 
 #[derive(Clone, Debug)]
 pub struct AppState {
     pg_pool: Arc<Pool<Postgres>>,
+    session_cache: Arc<SessionCache>,
+    jwt_keys: Arc<JwtKeys>,
+    login_limiter: Arc<LoginLimiter>,
+    trusted_proxies: Vec<IpAddr>,
+    features: Features,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let config = Config::load()?;
+
     let db_url = env::var("DATABASE_URL").unwrap();
     let pg_pool = Arc::new(
         PgPoolOptions::new()
-            .max_connections(5)
+            .max_connections(config.database.max_connections)
             .connect(&db_url)
             .await
             .unwrap(),
     );
 
+    if config.database.migrate_on_startup {
+        MIGRATOR
+            .run(pg_pool.as_ref())
+            .await
+            .map_err(io::Error::other)?;
+        log::info!("Database is up to date");
+    }
+
+    let session_cache = Arc::new(SessionCache::default());
+    let login_limiter = Arc::new(LoginLimiter::default());
+
+    let jwt_key_dir = env::var("JWT_KEY_DIR").unwrap_or("jwt_keys".to_string());
+    let jwt_keys = Arc::new(JwtKeys::load(Path::new(&jwt_key_dir))?);
+
+    // Clean up expired sessions in the background
+    let (purge_pool, purge_cache) = (pg_pool.clone(), session_cache.clone());
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(SESSION_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = purge_expired_sessions(&purge_pool, &purge_cache).await {
+                log::error!("Couldn't purge expired sessions: {e}");
+            }
+        }
+    });
+
+    let bind_address = config.server.bind_address.clone();
+    log::info!("Listening on {bind_address}");
+
     HttpServer::new(move || {
-        let cors = Cors::default()
-            .allowed_origin("https://mariokart.cc")
-            .allowed_origin("http://localhost:5173")
-            .allowed_methods(vec!["GET", "POST", "DELETE"])
+        let cors = config
+            .cors
+            .origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .allowed_methods(config.cors.methods.iter().map(String::as_str))
             .allowed_header(http::header::CONTENT_TYPE)
             .allowed_header(http::header::AUTHORIZATION);
 
         let state = AppState {
             pg_pool: pg_pool.clone(),
+            session_cache: session_cache.clone(),
+            jwt_keys: jwt_keys.clone(),
+            login_limiter: login_limiter.clone(),
+            trusted_proxies: config.server.trusted_proxies.clone(),
+            features: config.features.clone(),
         };
+        let features = config.features.clone();
 
         App::new()
             .app_data(Data::new(state))
+            .app_data(JsonConfig::default().error_handler(error::bad_request))
+            .app_data(QueryConfig::default().error_handler(error::bad_request))
+            .app_data(PathConfig::default().error_handler(error::bad_request))
             .wrap(cors)
+            .default_service(web::to(error::unknown_route))
             .service(list_players)
             .service(add_game)
             .service(get_previous_players)
+            .service(get_game)
+            .service(update_game)
+            .service(delete_game)
             .service(get_group_stats)
             .service(list_groups)
+            .service(list_games)
             .service(get_group)
             .service(create_group)
+            .service(update_group_settings)
             .service(player_history)
+            .service(player_rating_history)
             .service(player_name)
             .service(create_player)
             .service(get_group_badges)
+            .service(get_badge_tiers)
+            .service(update_badge_tiers)
             .service(player_best_streak)
             .service(head_to_head)
             .service(list_all_players)
@@ -67,8 +164,39 @@ async fn main() -> std::io::Result<()> {
             .service(login)
             .service(refresh_auth_token)
             .service(delete_session)
+            .service(list_sessions)
+            .service(revoke_session)
+            .service(start_two_factor_enrolment)
+            .service(confirm_two_factor_enrolment)
+            .service(disable_two_factor)
+            .service(verify_two_factor)
+            .service(list_admins)
+            .service(create_admin)
+            .service(delete_admin)
+            .service(change_password)
+            .service(list_seasons)
+            .service(create_season)
+            .service(get_season)
+            .service(close_season)
+            .service(group_records)
+            .service(list_group_roles)
+            .service(set_group_role)
+            .service(remove_group_role)
+            .service(list_audit_log)
+            .configure(|cfg| {
+                if features.achievements {
+                    cfg.service(list_achievements)
+                        .service(player_achievements)
+                        .service(group_achievements);
+                }
+                if features.api_keys {
+                    cfg.service(list_api_keys)
+                        .service(create_api_key)
+                        .service(delete_api_key);
+                }
+            })
     })
-    .bind(("0.0.0.0", 8080))?
+    .bind(bind_address)?
     .run()
     .await
 }
@@ -0,0 +1,135 @@
+use actix_web::{
+    get,
+    web::{self, Data, Query},
+    HttpResponse, Responder,
+};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access::MaybeCaller,
+    achievements::{find_rule, RULES},
+    error::{Error, Result},
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Achievement {
+    id: String,
+    name: String,
+    description: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UnlockedAchievement {
+    #[serde(flatten)]
+    achievement: Achievement,
+    player_id: i32,
+    player_name: String,
+    group_id: i32,
+    game_id: Option<i32>,
+    unlocked_at: NaiveDateTime,
+}
+
+fn achievement(id: String) -> Achievement {
+    match find_rule(&id) {
+        Some(rule) => Achievement {
+            id,
+            name: rule.name().to_string(),
+            description: rule.description().to_string(),
+        },
+        // Rule has since been removed, but the unlock is still on record
+        None => Achievement {
+            name: id.clone(),
+            description: String::new(),
+            id,
+        },
+    }
+}
+
+#[get("/achievements")]
+pub async fn list_achievements() -> impl Responder {
+    let achievements: Vec<_> = RULES
+        .iter()
+        .map(|rule| achievement(rule.id().to_string()))
+        .collect();
+
+    HttpResponse::Ok().json(achievements)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerAchievementsData {
+    group_id: Option<i32>,
+}
+
+async fn get_unlocked(
+    data: &AppState,
+    player_id: Option<i32>,
+    group_id: Option<i32>,
+) -> Result<Vec<UnlockedAchievement>> {
+    let achievements = sqlx::query!(
+        r#"SELECT
+            player_achievement.achievement,
+            player_achievement.player_id,
+            player.name as player_name,
+            player_achievement.group_id,
+            player_achievement.game_id,
+            player_achievement.unlocked_at as "unlocked_at: NaiveDateTime"
+        FROM player_achievement
+        INNER JOIN player ON player.id = player_achievement.player_id
+        WHERE ($1::int IS NULL OR player_achievement.player_id = $1)
+            AND ($2::int IS NULL OR player_achievement.group_id = $2)
+        ORDER BY player_achievement.unlocked_at DESC"#,
+        player_id,
+        group_id,
+    )
+    .fetch_all(data.pg_pool.as_ref())
+    .await?
+    .into_iter()
+    .map(|a| UnlockedAchievement {
+        achievement: achievement(a.achievement),
+        player_id: a.player_id,
+        player_name: a.player_name,
+        group_id: a.group_id,
+        game_id: a.game_id,
+        unlocked_at: a.unlocked_at,
+    })
+    .collect();
+
+    Ok(achievements)
+}
+
+#[get("/player/{player_id}/achievements")]
+pub async fn player_achievements(
+    data: Data<AppState>,
+    info: Query<PlayerAchievementsData>,
+    path: web::Path<i32>,
+    caller: MaybeCaller,
+) -> Result {
+    let player_id = path.into_inner();
+    let hidden = caller.hidden_groups(data.pg_pool.as_ref()).await?;
+    if info.group_id.is_some_and(|id| hidden.contains(&id)) {
+        return Err(Error::not_found("Group not found"));
+    }
+
+    let mut achievements = get_unlocked(&data, Some(player_id), info.group_id).await?;
+    achievements.retain(|a| !hidden.contains(&a.group_id));
+
+    Ok(HttpResponse::Ok().json(achievements))
+}
+
+#[get("/group/{group_id}/achievements")]
+pub async fn group_achievements(
+    data: Data<AppState>,
+    path: web::Path<i32>,
+    caller: MaybeCaller,
+) -> Result {
+    let group_id = path.into_inner();
+    caller.require_view(data.pg_pool.as_ref(), group_id).await?;
+
+    let achievements = get_unlocked(&data, None, Some(group_id)).await?;
+    Ok(HttpResponse::Ok().json(achievements))
+}
@@ -0,0 +1,226 @@
+use std::ops::DerefMut;
+
+use actix_web::{
+    delete, get, post, put,
+    web::{self, Data},
+    HttpResponse,
+};
+use bcrypt::DEFAULT_COST;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access::{Admin, Permission, Role},
+    audit::{self, Action, Entry},
+    error::{Error, Result},
+    passwords,
+    routes::roles::{is_last_owner, last_owner},
+    validation::FieldError,
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUser {
+    id: i32,
+    name: String,
+    site_admin: bool,
+}
+
+fn check_password_policy(field: &str, name: &str, password: &str) -> Vec<FieldError> {
+    passwords::policy_errors(name, password)
+        .into_iter()
+        .map(|message| FieldError::new(field, message))
+        .collect()
+}
+
+#[get("/admins")]
+pub async fn list_admins(data: Data<AppState>, admin: Admin) -> Result {
+    admin
+        .require(&data.pg_pool, Permission::ManageAdmins, &[])
+        .await?;
+
+    let admins = sqlx::query_as!(
+        AdminUser,
+        "SELECT id, username as name, site_admin FROM admin_user ORDER BY id"
+    )
+    .fetch_all(data.pg_pool.as_ref())
+    .await?;
+
+    Ok(HttpResponse::Ok().json(admins))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAdminData {
+    name: String,
+    password: String,
+    /// Whether the new admin can manage admins too
+    #[serde(default)]
+    site_admin: bool,
+}
+
+#[post("/admin")]
+pub async fn create_admin(
+    data: Data<AppState>,
+    payload: web::Json<CreateAdminData>,
+    caller: Admin,
+) -> Result {
+    caller
+        .require(&data.pg_pool, Permission::ManageAdmins, &[])
+        .await?;
+
+    let mut errors = check_password_policy("password", &payload.name, &payload.password);
+    if payload.name.trim().is_empty() {
+        errors.push(FieldError::new("name", "Name cannot be empty"));
+    }
+
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    let mut transaction = data.pg_pool.begin().await?;
+    let admin = sqlx::query_as!(
+        AdminUser,
+        r#"INSERT INTO admin_user (username, password_hash, site_admin)
+        VALUES ($1, $2, $3)
+        RETURNING id, username as name, site_admin"#,
+        payload.name.trim(),
+        bcrypt::hash(&payload.password, DEFAULT_COST).unwrap(),
+        payload.site_admin,
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+
+    let entry = Entry::new(Action::CreateAdmin, admin.id).after(&admin);
+    audit::record(transaction.deref_mut(), &caller, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(admin))
+}
+
+#[delete("/admin/{admin_id}")]
+pub async fn delete_admin(data: Data<AppState>, path: web::Path<i32>, caller: Admin) -> Result {
+    caller
+        .require(&data.pg_pool, Permission::ManageAdmins, &[])
+        .await?;
+
+    let admin_id = path.into_inner();
+    let mut transaction = data.pg_pool.begin().await?;
+    let admin = sqlx::query_as!(
+        AdminUser,
+        "SELECT id, username as name, site_admin FROM admin_user WHERE id = $1 FOR UPDATE",
+        admin_id
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?;
+
+    let Some(admin) = admin else {
+        return Err(Error::not_found("Admin not found"));
+    };
+
+    if admin_id == caller.id {
+        return Err(Error::conflict("Cannot delete your own account"));
+    }
+
+    // Only owners of every group the admin has a role in can delete them, and not if it would
+    // leave one of those groups without an owner
+    let roles = sqlx::query!(
+        "SELECT group_id, role FROM group_role WHERE user_id = $1",
+        admin_id
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+
+    let group_ids: Vec<_> = roles.iter().map(|r| r.group_id).collect();
+    caller
+        .require(&data.pg_pool, Permission::ManageGroup, &group_ids)
+        .await?;
+
+    for role in roles.iter().filter(|r| r.role == Role::Owner.as_str()) {
+        if is_last_owner(transaction.deref_mut(), role.group_id, admin_id).await? {
+            return Err(last_owner());
+        }
+    }
+
+    let sids = sqlx::query_scalar!(
+        "DELETE FROM admin_session WHERE user_id = $1 RETURNING id",
+        admin_id
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+
+    sqlx::query!("DELETE FROM admin_user WHERE id = $1", admin_id)
+        .execute(transaction.deref_mut())
+        .await?;
+
+    let entry = Entry::new(Action::DeleteAdmin, admin_id).before(&admin);
+    audit::record(transaction.deref_mut(), &caller, entry).await?;
+    transaction.commit().await?;
+
+    for sid in sids {
+        data.session_cache.revoke(sid);
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangePasswordData {
+    current_password: String,
+    new_password: String,
+}
+
+/// Changes the caller's password and logs out their other sessions
+#[put("/auth/password")]
+pub async fn change_password(
+    data: Data<AppState>,
+    payload: web::Json<ChangePasswordData>,
+    admin: Admin,
+) -> Result {
+    let password_hash = sqlx::query_scalar!(
+        "SELECT password_hash FROM admin_user WHERE id = $1",
+        admin.id
+    )
+    .fetch_one(data.pg_pool.as_ref())
+    .await?;
+
+    if !bcrypt::verify(&payload.current_password, &password_hash).unwrap_or(false) {
+        return Err(Error::validation(
+            "currentPassword",
+            "Password is incorrect",
+        ));
+    }
+
+    let errors = check_password_policy("newPassword", &admin.username, &payload.new_password);
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    let mut transaction = data.pg_pool.begin().await?;
+    sqlx::query!(
+        "UPDATE admin_user SET password_hash = $1 WHERE id = $2",
+        bcrypt::hash(&payload.new_password, DEFAULT_COST).unwrap(),
+        admin.id,
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+
+    let revoked = sqlx::query_scalar!(
+        "DELETE FROM admin_session WHERE user_id = $1 AND id != $2 RETURNING id",
+        admin.id,
+        admin.sid,
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+
+    let entry = Entry::new(Action::ChangePassword, admin.id);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    for sid in revoked {
+        data.session_cache.revoke(sid);
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
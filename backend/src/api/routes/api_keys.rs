@@ -0,0 +1,215 @@
+use std::ops::DerefMut;
+
+use actix_web::{
+    delete, get, post,
+    web::{self, Data},
+    HttpResponse,
+};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access::{Admin, Permission, Role},
+    api_keys::{generate_key, hash_key, Scope},
+    audit::{self, Action, Entry},
+    error::{Error, Result},
+    validation::FieldError,
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyInfo {
+    id: i32,
+    name: String,
+    scopes: Vec<String>,
+    group_ids: Vec<i32>,
+    created_by: Option<String>,
+    created_at: NaiveDateTime,
+    last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiKeyData {
+    name: String,
+    scopes: Vec<String>,
+    /// Groups the key can be used for. Defaults to every group that exists when it's created
+    group_ids: Option<Vec<i32>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedApiKey {
+    id: i32,
+    key: String,
+}
+
+/// Groups the admin is an owner of
+async fn owned_groups(data: &AppState, admin: &Admin) -> Result<Vec<i32>> {
+    let roles = admin.roles(&data.pg_pool).await?;
+    Ok(roles
+        .into_iter()
+        .filter(|(_, role)| *role == Role::Owner)
+        .map(|(group_id, _)| group_id)
+        .collect())
+}
+
+/// Lists the keys the admin created, or that can only be used for groups they own
+#[get("/api_keys")]
+pub async fn list_api_keys(data: Data<AppState>, admin: Admin) -> Result {
+    let owned_groups = owned_groups(&data, &admin).await?;
+    let keys = sqlx::query_as!(
+        ApiKeyInfo,
+        r#"SELECT
+            api_key.id,
+            api_key.name,
+            api_key.scopes,
+            api_key.group_ids,
+            admin_user.username as "created_by?",
+            api_key.created_at as "created_at: NaiveDateTime",
+            api_key.last_used_at as "last_used_at: NaiveDateTime"
+        FROM api_key
+        LEFT JOIN admin_user ON admin_user.id = api_key.created_by
+        WHERE api_key.created_by = $1 OR api_key.group_ids <@ $2
+        ORDER BY api_key.id"#,
+        admin.id,
+        &owned_groups,
+    )
+    .fetch_all(data.pg_pool.as_ref())
+    .await?;
+
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+/// Creates a key. The key itself is only ever returned here, as only its hash is stored
+#[post("/api_key")]
+pub async fn create_api_key(
+    data: Data<AppState>,
+    payload: web::Json<CreateApiKeyData>,
+    admin: Admin,
+) -> Result {
+    let mut errors = Vec::new();
+    if payload.name.trim().is_empty() {
+        errors.push(FieldError::new("name", "Name cannot be empty"));
+    }
+
+    if payload.scopes.is_empty() {
+        errors.push(FieldError::new("scopes", "At least one scope is required"));
+    }
+
+    for scope in &payload.scopes {
+        if scope.parse::<Scope>().is_err() {
+            let valid = Scope::ALL.iter().map(Scope::as_str).collect::<Vec<_>>();
+            errors.push(FieldError::new(
+                "scopes",
+                format!(
+                    "Unknown scope {scope:?}, expected one of {}",
+                    valid.join(", ")
+                ),
+            ));
+        }
+    }
+
+    if let Some(group_ids) = &payload.group_ids {
+        let existing = sqlx::query_scalar!("SELECT id FROM grp WHERE id = ANY($1)", group_ids)
+            .fetch_all(data.pg_pool.as_ref())
+            .await?;
+
+        for group_id in group_ids.iter().filter(|id| !existing.contains(id)) {
+            errors.push(FieldError::new(
+                "groupIds",
+                format!("Group {group_id} does not exist"),
+            ));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    // Admins can't give a key more access than they have themselves. Keys that aren't restricted
+    // to groups can be used for every group that exists now, but not ones created later
+    let group_ids = match &payload.group_ids {
+        Some(group_ids) => group_ids.clone(),
+        None => {
+            sqlx::query_scalar!("SELECT id FROM grp")
+                .fetch_all(data.pg_pool.as_ref())
+                .await?
+        }
+    };
+
+    if group_ids.is_empty() {
+        return Err(Error::validation(
+            "groupIds",
+            "A key must be usable for at least one group",
+        ));
+    }
+
+    for scope in payload.scopes.iter().filter_map(|s| s.parse().ok()) {
+        let permission = Permission::for_scope(scope);
+        admin.require(&data.pg_pool, permission, &group_ids).await?;
+    }
+
+    let key = generate_key();
+    let mut transaction = data.pg_pool.begin().await?;
+    let id = sqlx::query_scalar!(
+        "INSERT INTO api_key (name, key_hash, scopes, group_ids, created_by)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id",
+        payload.name.trim(),
+        hash_key(&key),
+        &payload.scopes,
+        &group_ids,
+        admin.id,
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+
+    // Never log the key itself
+    let created = CreateApiKeyData {
+        group_ids: Some(group_ids),
+        ..payload.into_inner()
+    };
+    let entry = Entry::new(Action::CreateApiKey, id).after(&created);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(CreatedApiKey { id, key }))
+}
+
+#[delete("/api_key/{key_id}")]
+pub async fn delete_api_key(data: Data<AppState>, path: web::Path<i32>, admin: Admin) -> Result {
+    let key_id = path.into_inner();
+    let mut transaction = data.pg_pool.begin().await?;
+    let key = sqlx::query!(
+        "SELECT created_by, name, scopes, group_ids FROM api_key WHERE id = $1 FOR UPDATE",
+        key_id
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?
+    .ok_or_else(|| Error::not_found("API key not found"))?;
+
+    // Only the admin who created the key, or an owner of all its groups, can revoke it
+    if key.created_by != Some(admin.id) {
+        let owned_groups = owned_groups(&data, &admin).await?;
+        if !key.group_ids.iter().all(|id| owned_groups.contains(id)) {
+            return Err(Error::Forbidden);
+        }
+    }
+
+    sqlx::query!("DELETE FROM api_key WHERE id = $1", key_id)
+        .execute(transaction.deref_mut())
+        .await?;
+
+    let before = CreateApiKeyData {
+        name: key.name,
+        scopes: key.scopes,
+        group_ids: Some(key.group_ids),
+    };
+    let entry = Entry::new(Action::DeleteApiKey, key_id).before(&before);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,115 @@
+use actix_web::{
+    get,
+    web::{Data, Query},
+    HttpResponse,
+};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    access::{Admin, Permission, Role},
+    audit::Action,
+    error::Result,
+    AppState,
+};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    id: i32,
+    created_at: NaiveDateTime,
+    admin_id: Option<i32>,
+    api_key_id: Option<i32>,
+    actor_name: String,
+    action: String,
+    group_id: Option<i32>,
+    target_id: Option<i32>,
+    before: Option<Value>,
+    after: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditFilter {
+    /// Name of the admin or API key that made the change
+    actor: Option<String>,
+    action: Option<String>,
+    group_id: Option<i32>,
+    target_id: Option<i32>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    /// Only entries older than this one, for fetching the next page
+    before_id: Option<i32>,
+    limit: Option<i64>,
+}
+
+/// Lists audit log entries, newest first. Admins can see changes to groups they own, and changes
+/// that aren't to a group (admins, API keys etc.) that they made or that were made to them. Admins
+/// who can manage admins can see every change that isn't to a group
+#[get("/audit")]
+pub async fn list_audit_log(
+    data: Data<AppState>,
+    info: Query<AuditFilter>,
+    admin: Admin,
+) -> Result {
+    let pool = data.pg_pool.as_ref();
+    let owned: Vec<i32> = admin
+        .roles(pool)
+        .await?
+        .into_iter()
+        .filter(|(_, role)| *role == Role::Owner)
+        .map(|(group_id, _)| group_id)
+        .collect();
+    let see_all_ungrouped = admin.has(pool, Permission::ManageAdmins, &[]).await?;
+    let on_admins: Vec<&str> = Action::ON_ADMINS.iter().map(Action::as_str).collect();
+
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"SELECT
+            id,
+            created_at as "created_at: NaiveDateTime",
+            admin_id,
+            api_key_id,
+            actor_name,
+            action,
+            group_id,
+            target_id,
+            before,
+            after
+        FROM audit_log
+        WHERE (group_id = ANY($1) OR (group_id IS NULL AND (
+                $10
+                OR admin_id = $11
+                OR (action = ANY($12) AND target_id = $11)
+            )))
+            AND ($2::text IS NULL OR actor_name = $2)
+            AND ($3::text IS NULL OR action = $3)
+            AND ($4::int IS NULL OR group_id = $4)
+            AND ($5::int IS NULL OR target_id = $5)
+            AND ($6::timestamp IS NULL OR created_at >= $6)
+            AND ($7::timestamp IS NULL OR created_at < $7)
+            AND ($8::int IS NULL OR id < $8)
+        ORDER BY id DESC
+        LIMIT $9"#,
+        &owned,
+        info.actor,
+        info.action,
+        info.group_id,
+        info.target_id,
+        info.from as _,
+        info.to as _,
+        info.before_id,
+        info.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+        see_all_ungrouped,
+        admin.id,
+        &on_admins as &[&str],
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
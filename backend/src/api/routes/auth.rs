@@ -1,43 +1,82 @@
+use std::ops::DerefMut;
+
 use actix_web::{
     delete, get,
-    http::header::ContentType,
+    http::header,
     post,
     web::{self, Data},
-    HttpResponse, Responder,
+    HttpRequest, HttpResponse,
 };
-use actix_web_httpauth::extractors::bearer::BearerAuth;
-use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use chrono::{Duration, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::AppState;
+use crate::{
+    access::{bearer_token, Admin},
+    audit::{self, Action, Entry},
+    error::{Error, Result},
+    jwt_keys::JwtKeys,
+    session_cache::SessionCache,
+    AppState,
+};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 enum TokenType {
     Refresh,
     Access,
+    TwoFactor,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
+pub struct Claims {
+    pub sub: String,
     exp: i64,
     nbf: i64,
-    sid: String,
+    pub sid: String,
+    token_type: TokenType,
+    /// Unique ID of a refresh token, so reused refresh tokens can be detected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jti: Option<Uuid>,
+}
+
+/// Issued when the password is correct but the admin has two-factor authentication enabled. It
+/// is swapped for the real tokens once a valid code is given
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorClaims {
+    pub sub: i32,
+    exp: i64,
     token_type: TokenType,
 }
 
-pub async fn is_authorised(token: &str) -> bool {
-    match decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(include_bytes!("../jwt_secret.dat")),
-        &Validation::default(),
-    ) {
-        Ok(token) => token.claims.token_type == TokenType::Access,
-        Err(_) => false,
+/// Claims of a valid access token whose session hasn't been revoked
+pub async fn authorised_claims(data: &AppState, token: &str) -> Result<Option<Claims>> {
+    let claims = match data.jwt_keys.decode::<Claims>(token) {
+        Ok(token) if token.claims.token_type == TokenType::Access => token.claims,
+        _ => return Ok(None),
+    };
+
+    let Ok(sid) = Uuid::parse_str(&claims.sid) else {
+        return Ok(None);
+    };
+
+    if data.session_cache.is_valid(sid) {
+        return Ok(Some(claims));
     }
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM admin_session WHERE id = $1) as "exists!""#,
+        sid
+    )
+    .fetch_one(data.pg_pool.as_ref())
+    .await?;
+
+    if !exists {
+        return Ok(None);
+    }
+
+    data.session_cache.insert(sid);
+    Ok(Some(claims))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -47,7 +86,7 @@ pub struct AuthData {
     password: String,
 }
 
-fn generate_refresh_token(user_id: i32, sid: Uuid) -> String {
+fn generate_refresh_token(keys: &JwtKeys, user_id: i32, sid: Uuid, jti: Uuid) -> String {
     // 5 year expiry
     let exp = (Utc::now() + Duration::days(5 * 365)).timestamp();
 
@@ -58,19 +97,15 @@ fn generate_refresh_token(user_id: i32, sid: Uuid) -> String {
         sub: user_id.to_string(),
         sid: sid.to_string(),
         token_type: TokenType::Refresh,
+        jti: Some(jti),
         nbf,
         exp,
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(include_bytes!("../jwt_secret.dat")),
-    )
-    .unwrap()
+    keys.encode(&claims)
 }
 
-fn generate_access_token(name: &str, sid: Uuid) -> String {
+fn generate_access_token(keys: &JwtKeys, name: &str, sid: Uuid) -> String {
     // 5 mins expiry
     let exp = (Utc::now() + Duration::minutes(5)).timestamp();
 
@@ -79,129 +114,394 @@ fn generate_access_token(name: &str, sid: Uuid) -> String {
         nbf: Utc::now().timestamp(),
         token_type: TokenType::Access,
         sid: sid.to_string(),
+        jti: None,
         exp,
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(include_bytes!("../jwt_secret.dat")),
-    )
-    .unwrap()
+    keys.encode(&claims)
+}
+
+fn generate_two_factor_token(keys: &JwtKeys, user_id: i32) -> String {
+    // 5 mins to enter the code
+    let exp = (Utc::now() + Duration::minutes(5)).timestamp();
+
+    let claims = TwoFactorClaims {
+        sub: user_id,
+        token_type: TokenType::TwoFactor,
+        exp,
+    };
+
+    keys.encode(&claims)
+}
+
+/// User ID from a valid two-factor token
+pub fn two_factor_user(keys: &JwtKeys, token: &str) -> Option<i32> {
+    match keys.decode::<TwoFactorClaims>(token) {
+        Ok(token) if token.claims.token_type == TokenType::TwoFactor => Some(token.claims.sub),
+        _ => None,
+    }
+}
+
+/// Sessions are removed once this old, even if they are still being used
+const SESSION_MAX_AGE_DAYS: i32 = 90;
+
+/// Sessions are removed if they haven't been refreshed for this long
+const SESSION_IDLE_DAYS: i32 = 14;
+
+/// How long the previous refresh token is still accepted after being replaced, for requests that
+/// started refreshing at the same time
+const REFRESH_REUSE_GRACE_SECS: f64 = 30.0;
+
+fn user_agent(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(header::USER_AGENT)
+        .and_then(|ua| ua.to_str().ok())
+        .map(|ua| ua.to_string())
 }
 
-async fn create_session(pool: &PgPool, user_id: i32) -> Uuid {
+/// IP the request came from. `X-Forwarded-For` can be set by anyone, so it is only followed
+/// through proxies listed in `server.trusted_proxies`
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let trusted_proxies = req
+        .app_data::<Data<AppState>>()
+        .map(|data| data.trusted_proxies.as_slice())
+        .unwrap_or_default();
+
+    let forwarded: Vec<_> = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+
+    // Each proxy appends the address it got the request from, so work back from the end until
+    // an address that isn't a trusted proxy
+    let mut ip = req.peer_addr()?.ip();
+    for forwarded_ip in forwarded.iter().rev() {
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+
+        match forwarded_ip.trim().parse() {
+            Ok(forwarded_ip) => ip = forwarded_ip,
+            Err(_) => break,
+        }
+    }
+
+    Some(ip.to_string())
+}
+
+/// Creates a session, returning its ID and the ID of its first refresh token
+async fn create_session(pool: &PgPool, user_id: i32, req: &HttpRequest) -> Result<(Uuid, Uuid)> {
     let sid = Uuid::new_v4();
+    let jti = Uuid::new_v4();
 
     sqlx::query!(
-        "INSERT INTO admin_session (id, user_id) VALUES ($1, $2)",
+        "INSERT INTO admin_session (id, user_id, user_agent, ip, refresh_token_id)
+        VALUES ($1, $2, $3, $4, $5)",
         sid,
         user_id,
+        user_agent(req),
+        client_ip(req),
+        jti,
     )
     .execute(pool)
-    .await
-    .unwrap();
+    .await?;
 
-    return sid;
+    Ok((sid, jti))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct AuthResponse {
+pub struct AuthResponse {
     access_token: String,
     refresh_token: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct TwoFactorChallenge {
+    two_factor_token: String,
+}
+
+/// Starts a new session for an admin who has fully logged in
+pub async fn start_session(
+    data: &AppState,
+    user_id: i32,
+    username: &str,
+    req: &HttpRequest,
+) -> Result<AuthResponse> {
+    let (sid, jti) = create_session(data.pg_pool.as_ref(), user_id, req).await?;
+
+    Ok(AuthResponse {
+        access_token: generate_access_token(&data.jwt_keys, username, sid),
+        refresh_token: generate_refresh_token(&data.jwt_keys, user_id, sid, jti),
+    })
+}
+
 #[post("/auth")]
-pub async fn login(data: Data<AppState>, info: web::Json<AuthData>) -> impl Responder {
+pub async fn login(data: Data<AppState>, info: web::Json<AuthData>, req: HttpRequest) -> Result {
+    let ip = client_ip(&req);
+    if let Some(retry_after) = data.login_limiter.retry_after(&info.name, ip.as_deref()) {
+        return Err(Error::TooManyRequests(retry_after));
+    }
+
     let admin_user = sqlx::query!(
-        "SELECT id, password_hash FROM admin_user WHERE username = $1;",
+        "SELECT id, password_hash, totp_enabled FROM admin_user WHERE username = $1;",
         info.name
     )
-    .fetch_one(data.pg_pool.as_ref())
-    .await;
-
-    let Ok(admin_user) = admin_user else {
-        return HttpResponse::Unauthorized()
-            .content_type(ContentType::plaintext())
-            .body("Invalid credentials");
+    .fetch_optional(data.pg_pool.as_ref())
+    .await?;
+
+    let valid = admin_user.as_ref().is_some_and(|admin_user| {
+        bcrypt::verify(&info.password, &admin_user.password_hash).unwrap_or(false)
+    });
+
+    let (Some(admin_user), true) = (admin_user, valid) else {
+        log::warn!(
+            "Failed login attempt for user {:?} from {}",
+            info.name,
+            ip.as_deref().unwrap_or("unknown IP")
+        );
+        data.login_limiter.record_failure(&info.name, ip.as_deref());
+
+        return Err(Error::unauthorised("Invalid credentials"));
     };
 
-    if !bcrypt::verify(&info.password, &admin_user.password_hash).unwrap_or(false) {
-        return HttpResponse::Unauthorized()
-            .content_type(ContentType::plaintext())
-            .body("Invalid credentials");
-    }
+    // Failures aren't cleared until the code has been checked too, otherwise knowing the
+    // password would allow unlimited attempts at the code
+    if admin_user.totp_enabled {
+        let resp = TwoFactorChallenge {
+            two_factor_token: generate_two_factor_token(&data.jwt_keys, admin_user.id),
+        };
 
-    let sid = create_session(data.pg_pool.as_ref(), admin_user.id).await;
-    let access_token = generate_access_token(&info.name, sid);
-    let refresh_token = generate_refresh_token(admin_user.id, sid);
+        return Ok(HttpResponse::Ok().json(resp));
+    }
 
-    let resp = AuthResponse {
-        access_token,
-        refresh_token,
-    };
+    data.login_limiter.record_success(&info.name);
 
-    HttpResponse::Ok().json(resp)
+    let resp = start_session(&data, admin_user.id, &info.name, &req).await?;
+    Ok(HttpResponse::Ok().json(resp))
 }
 
+/// Swaps a refresh token for a new access and refresh token. Each refresh token can only be
+/// used once - if an old one is presented again it has probably been stolen, so the whole session
+/// is revoked. The token that was just replaced is allowed for a few seconds, and gets the
+/// current refresh token back rather than rotating it again
 #[get("/auth/refresh")]
-pub async fn refresh_auth_token(data: Data<AppState>, auth: BearerAuth) -> impl Responder {
-    let token = decode::<Claims>(
-        auth.token(),
-        &DecodingKey::from_secret(include_bytes!("../jwt_secret.dat")),
-        &Validation::default(),
-    );
-
-    let Ok(token) = token else {
-        return HttpResponse::Unauthorized()
-            .content_type(ContentType::plaintext())
-            .body("Invalid refresh token");
+pub async fn refresh_auth_token(data: Data<AppState>, req: HttpRequest) -> Result {
+    let token = bearer_token(&req).unwrap_or_default();
+    let claims = match data.jwt_keys.decode::<Claims>(&token) {
+        Ok(token) if token.claims.token_type == TokenType::Refresh => token.claims,
+        _ => return Err(Error::unauthorised("Invalid refresh token")),
     };
 
-    let sid: Uuid = Uuid::parse_str(&token.claims.sid).unwrap();
-    let user_name = sqlx::query_scalar!(
-        r#"SELECT admin_user.username
+    let sid =
+        Uuid::parse_str(&claims.sid).map_err(|_| Error::unauthorised("Invalid refresh token"))?;
+    let mut transaction = data.pg_pool.begin().await?;
+
+    let session = sqlx::query!(
+        r#"SELECT
+            admin_session.refresh_token_id,
+            admin_session.previous_refresh_token_id,
+            admin_session.user_id,
+            admin_user.username,
+            admin_session.created_at > now() - make_interval(days => $2)
+                AND admin_session.last_refreshed_at > now() - make_interval(days => $3)
+                as "active!",
+            COALESCE(admin_session.refresh_token_rotated_at > now() - make_interval(secs => $4), false)
+                as "in_grace!"
         FROM admin_session
-        LEFT JOIN admin_user
-        ON admin_user.id = admin_session.user_id
-        WHERE admin_session.id = $1;"#,
-        sid
+        INNER JOIN admin_user ON admin_user.id = admin_session.user_id
+        WHERE admin_session.id = $1
+        FOR UPDATE OF admin_session"#,
+        sid,
+        SESSION_MAX_AGE_DAYS,
+        SESSION_IDLE_DAYS,
+        REFRESH_REUSE_GRACE_SECS,
     )
-    .fetch_one(data.pg_pool.as_ref())
-    .await;
+    .fetch_optional(transaction.deref_mut())
+    .await?;
 
-    let Ok(user_name) = user_name else {
-        return HttpResponse::Unauthorized()
-            .content_type(ContentType::plaintext())
-            .body("Session expired");
+    let Some(session) = session.filter(|s| s.active) else {
+        return Err(Error::unauthorised("Session expired"));
     };
 
-    let access_token = generate_access_token(&user_name, sid);
+    let just_replaced =
+        claims.jti.is_some() && session.previous_refresh_token_id == claims.jti && session.in_grace;
+    if let (true, Some(current_jti)) = (just_replaced, session.refresh_token_id) {
+        transaction.commit().await?;
+
+        let resp = AuthResponse {
+            access_token: generate_access_token(&data.jwt_keys, &session.username, sid),
+            refresh_token: generate_refresh_token(
+                &data.jwt_keys,
+                session.user_id,
+                sid,
+                current_jti,
+            ),
+        };
+
+        return Ok(HttpResponse::Ok().json(resp));
+    }
 
-    HttpResponse::Ok()
-        .content_type(ContentType::plaintext())
-        .body(access_token)
-}
+    if session.refresh_token_id != claims.jti {
+        sqlx::query!("DELETE FROM admin_session WHERE id = $1", sid)
+            .execute(transaction.deref_mut())
+            .await?;
+        transaction.commit().await?;
+        data.session_cache.revoke(sid);
 
-#[delete("/auth")]
-pub async fn delete_session(data: Data<AppState>, auth: BearerAuth) -> impl Responder {
-    let token = decode::<Claims>(
-        auth.token(),
-        &DecodingKey::from_secret(include_bytes!("../jwt_secret.dat")),
-        &Validation::default(),
-    );
-
-    let Ok(token) = token else {
-        return HttpResponse::Unauthorized()
-            .content_type(ContentType::plaintext())
-            .body("Invalid token");
+        return Err(Error::unauthorised("Refresh token has already been used"));
+    }
+
+    let jti = Uuid::new_v4();
+    sqlx::query!(
+        "UPDATE admin_session
+        SET refresh_token_id = $2,
+            previous_refresh_token_id = refresh_token_id,
+            refresh_token_rotated_at = now(),
+            last_refreshed_at = now(),
+            user_agent = $3,
+            ip = $4
+        WHERE id = $1",
+        sid,
+        jti,
+        user_agent(&req),
+        client_ip(&req),
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    transaction.commit().await?;
+
+    let resp = AuthResponse {
+        access_token: generate_access_token(&data.jwt_keys, &session.username, sid),
+        refresh_token: generate_refresh_token(&data.jwt_keys, session.user_id, sid, jti),
     };
 
-    let sid: Uuid = Uuid::parse_str(&token.claims.sid).unwrap();
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[delete("/auth")]
+pub async fn delete_session(data: Data<AppState>, req: HttpRequest) -> Result {
+    let token = bearer_token(&req).unwrap_or_default();
+    let claims = data
+        .jwt_keys
+        .decode::<Claims>(&token)
+        .map_err(|_| Error::unauthorised("Invalid token"))?
+        .claims;
+
+    let sid = Uuid::parse_str(&claims.sid).map_err(|_| Error::unauthorised("Invalid token"))?;
     sqlx::query!(r#"DELETE FROM admin_session WHERE id = $1"#, sid)
         .execute(data.pg_pool.as_ref())
-        .await
-        .unwrap();
+        .await?;
+    data.session_cache.revoke(sid);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Removes sessions that are too old or haven't been used recently
+pub async fn purge_expired_sessions(
+    pool: &PgPool,
+    session_cache: &SessionCache,
+) -> Result<(), sqlx::Error> {
+    let sids = sqlx::query_scalar!(
+        "DELETE FROM admin_session
+        WHERE created_at <= now() - make_interval(days => $1)
+            OR last_refreshed_at <= now() - make_interval(days => $2)
+        RETURNING id",
+        SESSION_MAX_AGE_DAYS,
+        SESSION_IDLE_DAYS,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for sid in sids {
+        session_cache.revoke(sid);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    id: Uuid,
+    created_at: NaiveDateTime,
+    last_refreshed_at: NaiveDateTime,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    current: bool,
+}
+
+/// Snapshot of a logged out session for the audit log
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RevokedSession {
+    id: Uuid,
+    created_at: NaiveDateTime,
+    last_refreshed_at: NaiveDateTime,
+    current: bool,
+}
+
+/// Lists the caller's sessions, most recently used first
+#[get("/auth/sessions")]
+pub async fn list_sessions(data: Data<AppState>, admin: Admin) -> Result {
+    let sessions = sqlx::query!(
+        r#"SELECT
+            admin_session.id,
+            admin_session.created_at as "created_at: NaiveDateTime",
+            admin_session.last_refreshed_at as "last_refreshed_at: NaiveDateTime",
+            admin_session.user_agent,
+            admin_session.ip
+        FROM admin_session
+        WHERE admin_session.user_id = $1
+        ORDER BY admin_session.last_refreshed_at DESC"#,
+        admin.id
+    )
+    .fetch_all(data.pg_pool.as_ref())
+    .await?
+    .into_iter()
+    .map(|s| Session {
+        current: s.id == admin.sid,
+        id: s.id,
+        created_at: s.created_at,
+        last_refreshed_at: s.last_refreshed_at,
+        user_agent: s.user_agent,
+        ip: s.ip,
+    })
+    .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+/// Logs out one of the caller's sessions, e.g. from a lost device
+#[delete("/auth/sessions/{session_id}")]
+pub async fn revoke_session(data: Data<AppState>, path: web::Path<Uuid>, admin: Admin) -> Result {
+    let sid = path.into_inner();
+    let mut transaction = data.pg_pool.begin().await?;
+    let deleted = sqlx::query!(
+        r#"DELETE FROM admin_session WHERE id = $1 AND user_id = $2
+        RETURNING
+            created_at as "created_at: NaiveDateTime",
+            last_refreshed_at as "last_refreshed_at: NaiveDateTime""#,
+        sid,
+        admin.id,
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?
+    .ok_or_else(|| Error::not_found("Session not found"))?;
+
+    // Without the device and IP, which only the admin should see
+    let before = RevokedSession {
+        id: sid,
+        created_at: deleted.created_at,
+        last_refreshed_at: deleted.last_refreshed_at,
+        current: sid == admin.sid,
+    };
+    let entry = Entry::new(Action::RevokeSession, admin.id).before(&before);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    data.session_cache.revoke(sid);
 
-    HttpResponse::NoContent().finish()
+    Ok(HttpResponse::NoContent().finish())
 }
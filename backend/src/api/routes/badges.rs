@@ -0,0 +1,291 @@
+use std::ops::DerefMut;
+
+use actix_web::{
+    get, put,
+    web::{self, Data, Query},
+    HttpResponse,
+};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+
+use crate::{
+    access::{Admin, MaybeCaller, Permission},
+    audit::{self, Action, Entry},
+    badges::DEFAULT_BADGE_TIERS,
+    error::{Error, Result},
+    utils::DateRange,
+    validation::FieldError,
+    AppState,
+};
+
+use super::{players::get_player_history, seasons::get_date_range};
+
+/// Score needed to earn a badge, either as a fixed score or a fraction of the group's max score
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum BadgeThreshold {
+    Score(i32),
+    Fraction(f32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BadgeTier {
+    name: String,
+    emoji: String,
+    threshold: BadgeThreshold,
+}
+
+/// Tiers given to new groups
+fn default_tiers() -> Vec<BadgeTier> {
+    DEFAULT_BADGE_TIERS
+        .iter()
+        .map(|&(name, emoji, fraction)| BadgeTier {
+            name: name.to_string(),
+            emoji: emoji.to_string(),
+            threshold: BadgeThreshold::Fraction(fraction),
+        })
+        .collect()
+}
+
+async fn get_tiers(pool: &PgPool, group_id: i32) -> Result<Vec<BadgeTier>> {
+    let tiers = sqlx::query!(
+        "SELECT name, emoji, score, fraction FROM badge_tier WHERE group_id = $1 ORDER BY id",
+        group_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|t| BadgeTier {
+        name: t.name,
+        emoji: t.emoji,
+        threshold: match (t.score, t.fraction) {
+            (Some(score), _) => BadgeThreshold::Score(score),
+            (None, fraction) => BadgeThreshold::Fraction(fraction.unwrap()),
+        },
+    })
+    .collect();
+
+    Ok(tiers)
+}
+
+async fn insert_tiers(conn: &mut PgConnection, group_id: i32, tiers: &[BadgeTier]) -> Result<()> {
+    for tier in tiers {
+        let (score, fraction) = match tier.threshold {
+            BadgeThreshold::Score(score) => (Some(score), None),
+            BadgeThreshold::Fraction(fraction) => (None, Some(fraction)),
+        };
+
+        sqlx::query!(
+            "INSERT INTO badge_tier (group_id, name, emoji, score, fraction)
+            VALUES ($1, $2, $3, $4, $5)",
+            group_id,
+            tier.name,
+            tier.emoji,
+            score,
+            fraction,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn create_default_tiers(conn: &mut PgConnection, group_id: i32) -> Result<()> {
+    insert_tiers(conn, group_id, &default_tiers()).await
+}
+
+#[get("/group/{group_id}/badge_tiers")]
+pub async fn get_badge_tiers(
+    data: Data<AppState>,
+    path: web::Path<i32>,
+    caller: MaybeCaller,
+) -> Result {
+    let group_id = path.into_inner();
+    caller.require_view(data.pg_pool.as_ref(), group_id).await?;
+
+    let tiers = get_tiers(data.pg_pool.as_ref(), group_id).await?;
+    Ok(HttpResponse::Ok().json(tiers))
+}
+
+fn validate_tiers(tiers: &[BadgeTier]) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    for (i, tier) in tiers.iter().enumerate() {
+        if tier.name.trim().is_empty() {
+            errors.push(FieldError::new(
+                format!("[{i}].name"),
+                "Name cannot be empty",
+            ));
+        } else if tiers[..i].iter().any(|t| t.name == tier.name) {
+            errors.push(FieldError::new(
+                format!("[{i}].name"),
+                "Name must be unique",
+            ));
+        }
+
+        match tier.threshold {
+            BadgeThreshold::Score(score) if score < 0 => errors.push(FieldError::new(
+                format!("[{i}].threshold.score"),
+                "Score cannot be negative",
+            )),
+            BadgeThreshold::Fraction(fraction) if !(fraction > 0.0 && fraction <= 1.0) => errors
+                .push(FieldError::new(
+                    format!("[{i}].threshold.fraction"),
+                    "Fraction must be more than 0 and at most 1",
+                )),
+            _ => {}
+        }
+    }
+
+    errors
+}
+
+/// Replaces all of the group's badge tiers
+#[put("/group/{group_id}/badge_tiers")]
+pub async fn update_badge_tiers(
+    data: Data<AppState>,
+    path: web::Path<i32>,
+    payload: web::Json<Vec<BadgeTier>>,
+    admin: Admin,
+) -> Result {
+    let group_id = path.into_inner();
+    let pool = data.pg_pool.as_ref();
+    admin
+        .require(pool, Permission::ManageGroup, &[group_id])
+        .await?;
+
+    let errors = validate_tiers(&payload);
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    let before = get_tiers(data.pg_pool.as_ref(), group_id).await?;
+
+    let mut transaction = data.pg_pool.begin().await?;
+    sqlx::query!("DELETE FROM badge_tier WHERE group_id = $1", group_id)
+        .execute(transaction.deref_mut())
+        .await?;
+
+    insert_tiers(&mut transaction, group_id, &payload).await?;
+
+    let entry = Entry::new(Action::UpdateBadgeTiers, None)
+        .group(group_id)
+        .before(&before)
+        .after(&*payload);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    let tiers = get_tiers(data.pg_pool.as_ref(), group_id).await?;
+    Ok(HttpResponse::Ok().json(tiers))
+}
+
+/// How many times a player has reached a tier
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BadgeCount {
+    name: String,
+    emoji: String,
+    count: usize,
+}
+
+/// Number of each badge, best tier first
+type Badges = Vec<BadgeCount>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BadgesWithId {
+    id: i32,
+    badges: Badges,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetBadgesData {
+    season_id: Option<i32>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+}
+
+async fn get_badges(pool: &PgPool, group_id: i32, range: DateRange) -> Result<Vec<BadgesWithId>> {
+    let max_score = sqlx::query!("SELECT max_score FROM grp WHERE id = $1", group_id)
+        .fetch_one(pool)
+        .await?
+        .max_score;
+
+    // Fractional tiers can't be used without a max score
+    let mut thresholds: Vec<(BadgeTier, f32)> = get_tiers(pool, group_id)
+        .await?
+        .into_iter()
+        .filter_map(|tier| {
+            let threshold = match tier.threshold {
+                BadgeThreshold::Score(score) => score as f32,
+                BadgeThreshold::Fraction(fraction) => fraction * max_score? as f32,
+            };
+            Some((tier, threshold))
+        })
+        .collect();
+
+    if thresholds.is_empty() && max_score.is_none() {
+        return Err(Error::not_found(
+            "Group does not have max score, so cannot have badges",
+        ));
+    }
+
+    // Highest first, so each score only gets the best badge it reaches
+    thresholds.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let player_ids = sqlx::query_scalar!(
+        "SELECT player_id FROM player_group WHERE group_id = $1",
+        group_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut all_badges = Vec::with_capacity(player_ids.len());
+    for id in player_ids {
+        let scores = get_player_history(pool, id, group_id, None, range).await?;
+        let mut badges: Badges = thresholds
+            .iter()
+            .map(|(tier, _)| BadgeCount {
+                name: tier.name.clone(),
+                emoji: tier.emoji.clone(),
+                count: 0,
+            })
+            .collect();
+
+        for score in scores {
+            let score = score as f32;
+            if let Some(i) = thresholds.iter().position(|(_, t)| score >= *t) {
+                badges[i].count += 1;
+            }
+        }
+
+        all_badges.push(BadgesWithId { badges, id });
+    }
+
+    Ok(all_badges)
+}
+
+#[get("/group/{group_id}/badges")]
+pub async fn get_group_badges(
+    data: Data<AppState>,
+    info: Query<GetBadgesData>,
+    path: web::Path<i32>,
+    caller: MaybeCaller,
+) -> Result {
+    let group_id = path.into_inner();
+    caller.require_view(data.pg_pool.as_ref(), group_id).await?;
+
+    let range = get_date_range(
+        data.pg_pool.as_ref(),
+        group_id,
+        info.season_id,
+        info.from,
+        info.to,
+    )
+    .await?;
+    let badges = get_badges(data.pg_pool.as_ref(), group_id, range).await?;
+    Ok(HttpResponse::Ok().json(badges))
+}
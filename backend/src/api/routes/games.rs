@@ -1,17 +1,27 @@
-use std::ops::DerefMut;
+use std::{collections::HashSet, ops::DerefMut};
 
 use actix_web::{
-    get,
-    http::{header::ContentType, Error},
-    post,
+    delete, get,
+    http::header::ContentType,
+    post, put,
     web::{self, Data, Query},
-    HttpResponse, Responder,
+    HttpResponse,
 };
-use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::NaiveDateTime;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 
-use super::auth::is_authorised;
-use crate::AppState;
+use super::seasons::rollover_seasons;
+use crate::{
+    access::{Caller, MaybeCaller, Permission},
+    achievements::{evaluate_game, reevaluate_players},
+    audit::{self, Action, Entry},
+    error::{Error, Result},
+    ratings::update_stored_ratings,
+    validation::FieldError,
+    AppState,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -27,28 +37,110 @@ pub struct GameScore {
     score: i32,
 }
 
+/// Checks the game against the group it is being added to. When updating, `existing` is the game
+/// as stored. Games already in a group can still be corrected, even if the group has since been
+/// archived or a player has left it, but no new games or players can be added
+async fn validate_game(
+    pool: &PgPool,
+    game: &Game,
+    existing: Option<&GameData>,
+) -> Result<Vec<FieldError>> {
+    let mut errors = Vec::new();
+
+    let group = sqlx::query!(
+        "SELECT max_score, archived FROM grp WHERE id = $1",
+        game.group_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(group) = group else {
+        errors.push(FieldError::new("groupId", "Group does not exist"));
+        return Ok(errors);
+    };
+
+    let existing = existing.filter(|existing| existing.group_id == game.group_id);
+    if group.archived && existing.is_none() {
+        errors.push(FieldError::new("groupId", "Group is archived"));
+    }
+
+    if game.scores.is_empty() {
+        errors.push(FieldError::new(
+            "scores",
+            "Game must have at least one score",
+        ));
+    }
+
+    let members: HashSet<i32> = sqlx::query_scalar!(
+        "SELECT player_id FROM player_group WHERE group_id = $1",
+        game.group_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+
+    let previous_players: HashSet<i32> = existing
+        .iter()
+        .flat_map(|existing| existing.scores.iter().map(|score| score.player_id))
+        .collect();
+
+    let mut seen = HashSet::new();
+    for (i, score) in game.scores.iter().enumerate() {
+        if !seen.insert(score.player_id) {
+            errors.push(FieldError::new(
+                format!("scores[{i}].playerId"),
+                "Player has more than one score",
+            ));
+        } else if !members.contains(&score.player_id)
+            && !previous_players.contains(&score.player_id)
+        {
+            errors.push(FieldError::new(
+                format!("scores[{i}].playerId"),
+                "Player is not in this group",
+            ));
+        }
+
+        if score.score < 0 {
+            errors.push(FieldError::new(
+                format!("scores[{i}].score"),
+                "Score cannot be negative",
+            ));
+        }
+
+        if let Some(max_score) = group.max_score {
+            if score.score > max_score {
+                errors.push(FieldError::new(
+                    format!("scores[{i}].score"),
+                    format!("Score cannot be more than {max_score}"),
+                ));
+            }
+        }
+    }
+
+    Ok(errors)
+}
+
 #[post("/game")]
-pub async fn add_game(
-    data: Data<AppState>,
-    payload: web::Json<Game>,
-    auth: BearerAuth,
-) -> Result<HttpResponse, Error> {
-    if !is_authorised(auth.token()).await {
-        return Ok(HttpResponse::Unauthorized()
-            .content_type(ContentType::plaintext())
-            .body("Not authorised to make this request"));
+pub async fn add_game(data: Data<AppState>, payload: web::Json<Game>, caller: Caller) -> Result {
+    let pool = data.pg_pool.as_ref();
+    caller
+        .require(pool, Permission::EditGames, &[payload.group_id])
+        .await?;
+
+    let errors = validate_game(data.pg_pool.as_ref(), &payload, None).await?;
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
     }
 
-    let mut transaction = data.pg_pool.begin().await.unwrap();
+    let mut transaction = data.pg_pool.begin().await?;
     sqlx::query!("INSERT INTO game (group_id) VALUES ($1);", payload.group_id)
         .execute(transaction.deref_mut())
-        .await
-        .unwrap();
+        .await?;
 
     let game_id = sqlx::query!("SELECT currval(pg_get_serial_sequence('game','id')) as id;")
         .fetch_one(transaction.deref_mut())
-        .await
-        .unwrap()
+        .await?
         .id
         .unwrap();
 
@@ -60,17 +152,229 @@ pub async fn add_game(
             score.player_id,
         )
         .execute(transaction.deref_mut())
-        .await
-        .unwrap();
+        .await?;
+    }
+
+    update_stored_ratings(&mut transaction, payload.group_id).await?;
+    if data.features.achievements {
+        evaluate_game(&mut transaction, payload.group_id, game_id as i32).await?;
     }
 
-    transaction.commit().await.unwrap();
+    let entry = Entry::new(Action::CreateGame, game_id as i32)
+        .group(payload.group_id)
+        .after(&*payload);
+    audit::record(transaction.deref_mut(), &caller, entry).await?;
+    transaction.commit().await?;
+
+    // The game is already saved, so a failed rollover shouldn't fail the request. It is retried
+    // when the next game is added
+    if let Err(e) = rollover_seasons(data.pg_pool.as_ref(), payload.group_id).await {
+        log::error!(
+            "Couldn't roll over seasons for group {}: {e}",
+            payload.group_id
+        );
+    }
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::plaintext())
         .body("Game added successfully"))
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GameData {
+    id: i32,
+    date: NaiveDateTime,
+    group_id: i32,
+    scores: Vec<GameScore>,
+}
+
+async fn game_group_id(pool: &PgPool, game_id: i32) -> Result<Option<i32>> {
+    let group_id = sqlx::query_scalar!("SELECT group_id FROM game WHERE id = $1", game_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(group_id)
+}
+
+async fn get_game_data(pool: &PgPool, game_id: i32) -> Result<Option<GameData>> {
+    let game = sqlx::query!(
+        r#"SELECT id, date as "date: NaiveDateTime", group_id FROM game WHERE id = $1"#,
+        game_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(game) = game else {
+        return Ok(None);
+    };
+
+    let scores = sqlx::query_as!(
+        GameScore,
+        "SELECT player_id, score FROM game_score WHERE game_id = $1 ORDER BY score DESC",
+        game_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(GameData {
+        id: game.id,
+        date: game.date,
+        group_id: game.group_id,
+        scores,
+    }))
+}
+
+#[get("/game/{game_id}")]
+pub async fn get_game(data: Data<AppState>, path: web::Path<i32>, caller: MaybeCaller) -> Result {
+    let Some(game) = get_game_data(data.pg_pool.as_ref(), path.into_inner()).await? else {
+        return Err(Error::not_found("Game not found"));
+    };
+
+    caller
+        .require_view(data.pg_pool.as_ref(), game.group_id)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(game))
+}
+
+#[put("/game/{game_id}")]
+pub async fn update_game(
+    data: Data<AppState>,
+    path: web::Path<i32>,
+    payload: web::Json<Game>,
+    caller: Caller,
+) -> Result {
+    // Needs access to both the group the game is moving from and the one it is moving to
+    let game_id = path.into_inner();
+    let mut group_ids = vec![payload.group_id];
+    group_ids.extend(game_group_id(data.pg_pool.as_ref(), game_id).await?);
+    let pool = data.pg_pool.as_ref();
+    caller
+        .require(pool, Permission::EditGames, &group_ids)
+        .await?;
+
+    let Some(before) = get_game_data(data.pg_pool.as_ref(), game_id).await? else {
+        return Err(Error::not_found("Game not found"));
+    };
+
+    let errors = validate_game(data.pg_pool.as_ref(), &payload, Some(&before)).await?;
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    let mut transaction = data.pg_pool.begin().await?;
+    let old_group_id = sqlx::query_scalar!(
+        "SELECT group_id FROM game WHERE id = $1 FOR UPDATE",
+        game_id
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?;
+
+    let Some(old_group_id) = old_group_id else {
+        return Err(Error::not_found("Game not found"));
+    };
+
+    sqlx::query!(
+        "UPDATE game SET group_id = $1 WHERE id = $2",
+        payload.group_id,
+        game_id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+
+    let old_player_ids = sqlx::query_scalar!(
+        "DELETE FROM game_score WHERE game_id = $1 RETURNING player_id",
+        game_id
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+
+    for score in &payload.scores {
+        sqlx::query!(
+            "INSERT INTO game_score (score, game_id, player_id) VALUES ($1, $2, $3)",
+            score.score,
+            game_id,
+            score.player_id,
+        )
+        .execute(transaction.deref_mut())
+        .await?;
+    }
+
+    // Lock the groups in order, so moving games in opposite directions can't deadlock
+    for group_id in [old_group_id, payload.group_id]
+        .into_iter()
+        .sorted()
+        .dedup()
+    {
+        update_stored_ratings(&mut transaction, group_id).await?;
+    }
+
+    if data.features.achievements {
+        let new_player_ids: Vec<i32> = payload.scores.iter().map(|s| s.player_id).collect();
+        if old_group_id == payload.group_id {
+            let player_ids = old_player_ids.into_iter().chain(new_player_ids);
+            let player_ids: Vec<i32> = player_ids.sorted().dedup().collect();
+            reevaluate_players(&mut transaction, old_group_id, &player_ids).await?;
+        } else {
+            reevaluate_players(&mut transaction, old_group_id, &old_player_ids).await?;
+            reevaluate_players(&mut transaction, payload.group_id, &new_player_ids).await?;
+        }
+    }
+
+    let entry = Entry::new(Action::UpdateGame, game_id)
+        .group(payload.group_id)
+        .before(&before)
+        .after(&*payload);
+    audit::record(transaction.deref_mut(), &caller, entry).await?;
+    transaction.commit().await?;
+
+    let game = get_game_data(data.pg_pool.as_ref(), game_id).await?;
+    Ok(HttpResponse::Ok().json(game))
+}
+
+#[delete("/game/{game_id}")]
+pub async fn delete_game(data: Data<AppState>, path: web::Path<i32>, caller: Caller) -> Result {
+    let game_id = path.into_inner();
+    let group_ids = Vec::from_iter(game_group_id(data.pg_pool.as_ref(), game_id).await?);
+    let pool = data.pg_pool.as_ref();
+    caller
+        .require(pool, Permission::EditGames, &group_ids)
+        .await?;
+
+    let before = get_game_data(data.pg_pool.as_ref(), game_id).await?;
+
+    let mut transaction = data.pg_pool.begin().await?;
+    let player_ids = sqlx::query_scalar!(
+        "DELETE FROM game_score WHERE game_id = $1 RETURNING player_id",
+        game_id
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+
+    let group_id =
+        sqlx::query_scalar!("DELETE FROM game WHERE id = $1 RETURNING group_id", game_id)
+            .fetch_optional(transaction.deref_mut())
+            .await?;
+
+    let Some(group_id) = group_id else {
+        return Err(Error::not_found("Game not found"));
+    };
+
+    update_stored_ratings(&mut transaction, group_id).await?;
+    if data.features.achievements {
+        reevaluate_players(&mut transaction, group_id, &player_ids).await?;
+    }
+
+    let entry = Entry::new(Action::DeleteGame, game_id)
+        .group(group_id)
+        .before(&before);
+    audit::record(transaction.deref_mut(), &caller, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct GroupIdData {
@@ -81,7 +385,12 @@ pub struct GroupIdData {
 pub async fn get_previous_players(
     data: Data<AppState>,
     info: Query<GroupIdData>,
-) -> impl Responder {
+    caller: MaybeCaller,
+) -> Result {
+    caller
+        .require_view(data.pg_pool.as_ref(), info.group_id)
+        .await?;
+
     let players = sqlx::query!(
         "SELECT player_id
         FROM game
@@ -93,9 +402,8 @@ pub async fn get_previous_players(
         info.group_id
     )
     .fetch_all(data.pg_pool.as_ref())
-    .await
-    .unwrap();
+    .await?;
 
     let player_ids: Vec<_> = players.iter().map(|p| p.player_id).collect();
-    HttpResponse::Ok().json(player_ids)
+    Ok(HttpResponse::Ok().json(player_ids))
 }
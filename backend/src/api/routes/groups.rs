@@ -1,26 +1,36 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::DerefMut};
 
 use actix_web::{
-    delete, get,
-    http::header::ContentType,
-    post,
+    delete, get, post, put,
     web::{self, Data, Path, Query},
-    HttpResponse, Responder,
+    HttpResponse,
 };
-use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::{DateTime, NaiveDateTime};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use urlencoding::decode;
 
 use crate::{
-    routes::players::{Player, PlayerStats},
-    utils::std_dev,
+    access::{Admin, Caller, MaybeCaller, Permission, Role},
+    audit::{self, Action, Entry},
+    error::{Error, Result},
+    ratings::{
+        elo::{Elo, DEFAULT_INITIAL_RATING, DEFAULT_K_FACTOR},
+        get_elo_config, get_rated_games, get_stored_ratings,
+        glicko::Glicko,
+        update_stored_ratings,
+    },
+    routes::{
+        players::{Player, PlayerStats},
+        seasons::get_date_range,
+    },
+    utils::{std_dev, DateRange},
+    validation::FieldError,
     AppState,
 };
 
-use super::auth::is_authorised;
-use super::players::get_player_history;
+use super::badges::create_default_tiers;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -29,14 +39,17 @@ struct Group {
     name: String,
     max_score: Option<i32>,
     archived: bool,
+    elo_k_factor: f32,
+    elo_initial_rating: f32,
+    private: bool,
 }
 
 #[get("/groups")]
-pub async fn list_groups(data: Data<AppState>) -> impl Responder {
-    let groups = sqlx::query!("SELECT * FROM grp")
+pub async fn list_groups(data: Data<AppState>, caller: MaybeCaller) -> Result {
+    let hidden = caller.hidden_groups(data.pg_pool.as_ref()).await?;
+    let groups = sqlx::query!("SELECT * FROM grp WHERE id != ALL($1)", &hidden)
         .fetch_all(data.pg_pool.as_ref())
-        .await
-        .unwrap();
+        .await?;
 
     let groups: Vec<_> = groups
         .iter()
@@ -45,25 +58,33 @@ pub async fn list_groups(data: Data<AppState>) -> impl Responder {
             name: g.name.to_string(),
             max_score: g.max_score,
             archived: g.archived,
+            elo_k_factor: g.elo_k_factor,
+            elo_initial_rating: g.elo_initial_rating,
+            private: g.private,
         })
         .collect();
-    HttpResponse::Ok().json(groups)
+    Ok(HttpResponse::Ok().json(groups))
 }
 
 #[get("/group/{group_id}")]
-pub async fn get_group(data: Data<AppState>, path: web::Path<i32>) -> impl Responder {
+pub async fn get_group(data: Data<AppState>, path: web::Path<i32>, caller: MaybeCaller) -> Result {
     let group_id = path.into_inner();
+    caller.require_view(data.pg_pool.as_ref(), group_id).await?;
+
     let group = sqlx::query!("SELECT * FROM grp WHERE id = $1", group_id)
-        .fetch_one(data.pg_pool.as_ref())
-        .await
-        .unwrap();
+        .fetch_optional(data.pg_pool.as_ref())
+        .await?
+        .ok_or_else(|| Error::not_found("Group not found"))?;
 
-    HttpResponse::Ok().json(Group {
+    Ok(HttpResponse::Ok().json(Group {
         id: group.id,
         name: group.name.to_string(),
         max_score: group.max_score,
         archived: group.archived,
-    })
+        elo_k_factor: group.elo_k_factor,
+        elo_initial_rating: group.elo_initial_rating,
+        private: group.private,
+    }))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -71,54 +92,178 @@ pub async fn get_group(data: Data<AppState>, path: web::Path<i32>) -> impl Respo
 pub struct CreateGroupData {
     name: String,
     max_score: Option<i32>,
+    elo_k_factor: Option<f32>,
+    elo_initial_rating: Option<f32>,
+    /// Private groups can only be seen by admins with a role in them
+    #[serde(default)]
+    private: bool,
 }
 
+/// Creates a group, making the admin that created it its owner
 #[post("/group")]
 pub async fn create_group(
     data: Data<AppState>,
     payload: web::Json<CreateGroupData>,
-    auth: BearerAuth,
-) -> impl Responder {
-    if !is_authorised(auth.token()).await {
-        return HttpResponse::Unauthorized()
-            .content_type(ContentType::plaintext())
-            .body("Not authorised to make this request");
-    }
+    admin: Admin,
+) -> Result {
+    admin
+        .require(&data.pg_pool, Permission::CreateGroup, &[])
+        .await?;
 
+    let mut transaction = data.pg_pool.begin().await?;
     let group = sqlx::query!(
-        r#"INSERT INTO grp (name, max_score)
-        VALUES ($1, $2)
-        RETURNING id, name, max_score, archived"#,
+        r#"INSERT INTO grp (name, max_score, elo_k_factor, elo_initial_rating, private)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, name, max_score, archived, elo_k_factor, elo_initial_rating, private"#,
         payload.name,
         payload.max_score,
+        payload.elo_k_factor.unwrap_or(DEFAULT_K_FACTOR),
+        payload.elo_initial_rating.unwrap_or(DEFAULT_INITIAL_RATING),
+        payload.private,
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO group_role (user_id, group_id, role) VALUES ($1, $2, $3)",
+        admin.id,
+        group.id,
+        Role::Owner.as_str(),
     )
-    .fetch_one(data.pg_pool.as_ref())
-    .await
-    .unwrap();
+    .execute(transaction.deref_mut())
+    .await?;
 
-    HttpResponse::Ok().json(Group {
+    create_default_tiers(&mut transaction, group.id).await?;
+
+    let group = Group {
         id: group.id,
         name: group.name.to_string(),
         max_score: group.max_score,
         archived: group.archived,
-    })
+        elo_k_factor: group.elo_k_factor,
+        elo_initial_rating: group.elo_initial_rating,
+        private: group.private,
+    };
+
+    let entry = Entry::new(Action::CreateGroup, group.id)
+        .group(group.id)
+        .after(&group);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(group))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct GetStatsData {
-    n: Option<i32>, // Number of games
-    skip_most_recent: bool,
+pub struct GroupSettingsData {
+    elo_k_factor: Option<f32>,
+    elo_initial_rating: Option<f32>,
 }
 
-#[get("/group/{group_id}/stats")]
-pub async fn get_group_stats(
+/// Changes the group's rating settings. Settings that aren't given are left as they are
+#[put("/group/{group_id}/settings")]
+pub async fn update_group_settings(
     data: Data<AppState>,
-    info: web::Query<GetStatsData>,
     path: web::Path<i32>,
-) -> impl Responder {
+    payload: web::Json<GroupSettingsData>,
+    admin: Admin,
+) -> Result {
     let group_id = path.into_inner();
+    let pool = data.pg_pool.as_ref();
+    admin
+        .require(pool, Permission::ManageGroup, &[group_id])
+        .await?;
+
+    let mut errors = Vec::new();
+    if payload
+        .elo_k_factor
+        .is_some_and(|k| !k.is_finite() || k <= 0.0)
+    {
+        errors.push(FieldError::new(
+            "eloKFactor",
+            "K-factor must be more than 0",
+        ));
+    }
+    if payload.elo_initial_rating.is_some_and(|r| !r.is_finite()) {
+        errors.push(FieldError::new(
+            "eloInitialRating",
+            "Initial rating must be a number",
+        ));
+    }
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    let mut transaction = data.pg_pool.begin().await?;
+    let before = sqlx::query!("SELECT * FROM grp WHERE id = $1 FOR UPDATE", group_id)
+        .fetch_optional(transaction.deref_mut())
+        .await?
+        .ok_or_else(|| Error::not_found("Group not found"))?;
+
+    let group = sqlx::query!(
+        r#"UPDATE grp
+        SET elo_k_factor = COALESCE($2, elo_k_factor),
+            elo_initial_rating = COALESCE($3, elo_initial_rating)
+        WHERE id = $1
+        RETURNING id, name, max_score, archived, elo_k_factor, elo_initial_rating, private"#,
+        group_id,
+        payload.elo_k_factor,
+        payload.elo_initial_rating,
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+
+    // Elo ratings aren't stored - they are replayed with these settings on every request, so the
+    // change applies to past games straight away. Stored ratings are refreshed at the same time
+    update_stored_ratings(&mut transaction, group_id).await?;
+
+    let before = Group {
+        id: before.id,
+        name: before.name,
+        max_score: before.max_score,
+        archived: before.archived,
+        elo_k_factor: before.elo_k_factor,
+        elo_initial_rating: before.elo_initial_rating,
+        private: before.private,
+    };
+    let group = Group {
+        id: group.id,
+        name: group.name,
+        max_score: group.max_score,
+        archived: group.archived,
+        elo_k_factor: group.elo_k_factor,
+        elo_initial_rating: group.elo_initial_rating,
+        private: group.private,
+    };
+
+    let entry = Entry::new(Action::UpdateGroupSettings, group_id)
+        .group(group_id)
+        .before(&before)
+        .after(&group);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(group))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetStatsData {
+    n: Option<i32>, // Number of games
+    skip_most_recent: bool,
+    season_id: Option<i32>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+}
 
+pub async fn get_stats(
+    pool: &PgPool,
+    group_id: i32,
+    n: Option<i32>,
+    skip_most_recent: bool,
+    range: DateRange,
+) -> Result<Vec<PlayerStats>> {
     // Players in group
     let player_games = sqlx::query!(
         r#"SELECT
@@ -130,12 +275,15 @@ pub async fn get_group_stats(
         INNER JOIN game_score ON game_score.player_id = player.id
         INNER JOIN game ON game_score.game_id = game.id
         WHERE game.group_id = $1
+            AND ($2::timestamp IS NULL OR game.date >= $2)
+            AND ($3::timestamp IS NULL OR game.date < $3)
         ORDER BY date DESC"#,
         group_id,
+        range.from as _,
+        range.to as _,
     )
-    .fetch_all(data.pg_pool.as_ref())
-    .await
-    .unwrap();
+    .fetch_all(pool)
+    .await?;
 
     // Highest score for each game
     let games = sqlx::query!(
@@ -144,24 +292,49 @@ pub async fn get_group_stats(
         INNER JOIN game_score ON game.id = game_score.game_id
         GROUP BY game.id"
     )
-    .fetch_all(data.pg_pool.as_ref())
-    .await
-    .unwrap();
+    .fetch_all(pool)
+    .await?;
 
     let games: HashMap<_, _> = games.iter().map(|g| (g.id, g.max_score.unwrap())).collect();
 
-    let most_recent_id = match info.skip_most_recent {
+    let most_recent_id = match skip_most_recent {
         true => sqlx::query!(
-            "SELECT game.id FROM game WHERE game.group_id = $1 ORDER BY date DESC LIMIT 1",
+            "SELECT game.id FROM game
+            WHERE game.group_id = $1
+                AND ($2::timestamp IS NULL OR game.date >= $2)
+                AND ($3::timestamp IS NULL OR game.date < $3)
+            ORDER BY date DESC
+            LIMIT 1",
             group_id,
+            range.from as _,
+            range.to as _,
         )
-        .fetch_one(data.pg_pool.as_ref())
-        .await
-        .ok()
+        .fetch_optional(pool)
+        .await?
         .map(|x| x.id),
         false => None,
     };
 
+    // Ratings are cumulative, so replay every game rather than just the last n
+    let mut elo = Elo::new(get_elo_config(pool, group_id).await?);
+    let mut glicko = Glicko::new();
+    for game in get_rated_games(pool, group_id, range).await? {
+        if Some(game.id) != most_recent_id {
+            elo.play(&game.scores);
+            glicko.play(&game.scores);
+        }
+    }
+
+    // Stored ratings cover every game, so can only be used when nothing is being left out
+    let stored_ratings = match most_recent_id.is_none() && range.is_unbounded() {
+        true => get_stored_ratings(pool, group_id).await?,
+        false => HashMap::new(),
+    };
+    let glicko_ratings = match stored_ratings.is_empty() {
+        true => glicko.ratings().clone(),
+        false => stored_ratings,
+    };
+
     // Player ID to stats
     let mut players: HashMap<i32, PlayerStats> = HashMap::new();
     for player_game in player_games {
@@ -178,10 +351,16 @@ pub async fn get_group_stats(
             wins: 0,
             games: 0,
             std_dev: 0.0,
+            elo: elo.rating(player_game.player_id),
+            rating: glicko_ratings
+                .get(&player_game.player_id)
+                .copied()
+                .unwrap_or_default(),
+            conservative_rating: 0.0,
         });
 
         // Skip if already got the n games
-        if let Some(n) = info.n {
+        if let Some(n) = n {
             if player.games >= n {
                 continue;
             }
@@ -196,20 +375,58 @@ pub async fn get_group_stats(
         player.std_dev += player_game.points.pow(2) as f32; // Sum squared
     }
 
-    let values = players
+    let stats = players
         .into_values()
         .map(|p| PlayerStats {
-            std_dev: std_dev(p.points as f32, p.std_dev as f32, p.games),
+            std_dev: std_dev(p.points as f32, p.std_dev, p.games),
+            conservative_rating: p.rating.conservative(),
             ..p
         })
+        .sorted_by(|a, b| b.conservative_rating.total_cmp(&a.conservative_rating))
         .collect_vec();
 
-    HttpResponse::Ok().json(values)
+    Ok(stats)
+}
+
+#[get("/group/{group_id}/stats")]
+pub async fn get_group_stats(
+    data: Data<AppState>,
+    info: web::Query<GetStatsData>,
+    path: web::Path<i32>,
+    caller: MaybeCaller,
+) -> Result {
+    let group_id = path.into_inner();
+    caller.require_view(data.pg_pool.as_ref(), group_id).await?;
+
+    let range = get_date_range(
+        data.pg_pool.as_ref(),
+        group_id,
+        info.season_id,
+        info.from,
+        info.to,
+    )
+    .await?;
+
+    let stats = get_stats(
+        data.pg_pool.as_ref(),
+        group_id,
+        info.n,
+        info.skip_most_recent,
+        range,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(stats))
 }
 
 #[get("/group/{group_id}/players")]
-pub async fn list_players(data: Data<AppState>, path: web::Path<i32>) -> impl Responder {
+pub async fn list_players(
+    data: Data<AppState>,
+    path: web::Path<i32>,
+    caller: MaybeCaller,
+) -> Result {
     let group_id = path.into_inner();
+    caller.require_view(data.pg_pool.as_ref(), group_id).await?;
 
     let players = sqlx::query!(
         r#"SELECT player.id as id, name
@@ -220,10 +437,9 @@ pub async fn list_players(data: Data<AppState>, path: web::Path<i32>) -> impl Re
         group_id,
     )
     .fetch_all(data.pg_pool.as_ref())
-    .await
-    .unwrap();
+    .await?;
 
-    HttpResponse::Ok().json(
+    Ok(HttpResponse::Ok().json(
         players
             .into_iter()
             .map(|p| Player {
@@ -231,93 +447,140 @@ pub async fn list_players(data: Data<AppState>, path: web::Path<i32>) -> impl Re
                 id: p.id,
             })
             .collect::<Vec<_>>(),
-    )
+    ))
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+const DEFAULT_GAME_LOG_LIMIT: i64 = 20;
+const MAX_GAME_LOG_LIMIT: i64 = 100;
+
+/// Position in the game log, after the last game of a page. Holds the game's date as well as its
+/// ID, so the next page still works if that game is deleted
+fn encode_cursor(date: NaiveDateTime, id: i32) -> String {
+    format!("{}_{id}", date.and_utc().timestamp_micros())
+}
+
+fn parse_cursor(cursor: &str) -> Result<(NaiveDateTime, i32)> {
+    let invalid = || Error::BadRequest("Invalid cursor".to_string());
+    let (micros, id) = cursor.split_once('_').ok_or_else(invalid)?;
+    let date = DateTime::from_timestamp_micros(micros.parse().map_err(|_| invalid())?)
+        .ok_or_else(invalid)?
+        .naive_utc();
+
+    Ok((date, id.parse().map_err(|_| invalid())?))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct Badges {
-    star: usize,
-    gold: usize,
-    silver: usize,
-    bronze: usize,
+pub struct GameLogData {
+    /// `nextCursor` from the previous page
+    cursor: Option<String>,
+    limit: Option<i64>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct BadgesWithId {
-    id: i32,
-    badges: Badges,
+pub struct GameLogScore {
+    player_id: i32,
+    name: String,
+    score: i32,
 }
 
-struct NoMaxScoreErr;
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GameLogEntry {
+    id: i32,
+    date: NaiveDateTime,
+    scores: Vec<GameLogScore>,
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct GetBadgesData {
-    ids: String,
-    group_id: i32,
+pub struct GameLog {
+    games: Vec<GameLogEntry>,
+    next_cursor: Option<String>,
 }
 
-async fn get_badges(pool: &PgPool, group_id: i32) -> Result<Vec<BadgesWithId>, NoMaxScoreErr> {
-    let max_score = sqlx::query!("SELECT max_score FROM grp WHERE id = $1", group_id)
-        .fetch_one(pool)
-        .await
-        .unwrap()
-        .max_score;
-
-    let max_score = match max_score {
-        Some(n) => n,
-        None => return Err(NoMaxScoreErr),
-    } as f32;
-
-    let star_score = max_score;
-    let gold_score = 0.94 * max_score;
-    let silver_score = 0.88 * max_score;
-    let bronze_score = 0.83 * max_score;
-
-    let player_ids = sqlx::query_scalar!(
-        "SELECT player_id FROM player_group WHERE group_id = $1",
-        group_id
+#[get("/group/{group_id}/games")]
+pub async fn list_games(
+    data: Data<AppState>,
+    info: Query<GameLogData>,
+    path: web::Path<i32>,
+    caller: MaybeCaller,
+) -> Result {
+    let group_id = path.into_inner();
+    caller.require_view(data.pg_pool.as_ref(), group_id).await?;
+
+    let limit = info
+        .limit
+        .unwrap_or(DEFAULT_GAME_LOG_LIMIT)
+        .clamp(1, MAX_GAME_LOG_LIMIT);
+
+    let cursor = info.cursor.as_deref().map(parse_cursor).transpose()?;
+
+    // Fetch one extra game to know if there is another page
+    let games = sqlx::query!(
+        r#"SELECT id, date as "date: NaiveDateTime"
+        FROM game
+        WHERE group_id = $1
+            AND ($2::timestamp IS NULL OR date >= $2)
+            AND ($3::timestamp IS NULL OR date < $3)
+            AND ($4::timestamp IS NULL OR (date, id) < ($4, $5))
+        ORDER BY date DESC, id DESC
+        LIMIT $6"#,
+        group_id,
+        info.from as _,
+        info.to as _,
+        cursor.map(|(date, _)| date) as _,
+        cursor.map(|(_, id)| id),
+        limit + 1,
     )
-    .fetch_all(pool)
-    .await
-    .unwrap();
-
-    let mut all_badges = Vec::with_capacity(player_ids.len());
-    for id in player_ids {
-        let scores = get_player_history(pool, id, group_id, None).await;
-        let mut badges: Badges = Default::default();
-
-        for score in scores {
-            let score = score as f32;
-            if score >= star_score {
-                badges.star += 1;
-            } else if score >= gold_score {
-                badges.gold += 1;
-            } else if score >= silver_score {
-                badges.silver += 1;
-            } else if score >= bronze_score {
-                badges.bronze += 1;
-            }
-        }
+    .fetch_all(data.pg_pool.as_ref())
+    .await?;
+
+    let has_more = games.len() as i64 > limit;
+    let games = games.into_iter().take(limit as usize).collect_vec();
+    let game_ids = games.iter().map(|g| g.id).collect_vec();
 
-        all_badges.push(BadgesWithId { badges, id });
+    let scores = sqlx::query!(
+        r#"SELECT game_score.game_id, game_score.player_id, player.name, game_score.score
+        FROM game_score
+        INNER JOIN player ON player.id = game_score.player_id
+        WHERE game_score.game_id = ANY($1)
+        ORDER BY game_score.score DESC"#,
+        &game_ids,
+    )
+    .fetch_all(data.pg_pool.as_ref())
+    .await?;
+
+    let mut scores_by_game: HashMap<i32, Vec<GameLogScore>> = HashMap::new();
+    for score in scores {
+        scores_by_game
+            .entry(score.game_id)
+            .or_default()
+            .push(GameLogScore {
+                player_id: score.player_id,
+                name: score.name,
+                score: score.score,
+            });
     }
 
-    Ok(all_badges)
-}
+    let next_cursor = match has_more {
+        true => games.last().map(|g| encode_cursor(g.date, g.id)),
+        false => None,
+    };
 
-#[get("/group/{group_id}/badges")]
-pub async fn get_group_badges(data: Data<AppState>, path: web::Path<i32>) -> impl Responder {
-    let group_id = path.into_inner();
-    let badges = get_badges(data.pg_pool.as_ref(), group_id).await;
-    match badges {
-        Ok(badges) => HttpResponse::Ok().json(badges),
-        Err(_) => HttpResponse::NotFound()
-            .content_type(ContentType::plaintext())
-            .body("Group does not have max score, so cannot have badges"),
-    }
+    let games = games
+        .into_iter()
+        .map(|g| GameLogEntry {
+            id: g.id,
+            date: g.date,
+            scores: scores_by_game.remove(&g.id).unwrap_or_default(),
+        })
+        .collect_vec();
+
+    Ok(HttpResponse::Ok().json(GameLog { games, next_cursor }))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -325,6 +588,9 @@ pub async fn get_group_badges(data: Data<AppState>, path: web::Path<i32>) -> imp
 pub struct HeadToHeadData {
     ids: String,
     n: Option<i32>,
+    season_id: Option<i32>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -362,22 +628,26 @@ struct CommonPlayerGame {
 async fn get_common_player_games(
     ids: &[i32],
     group_id: i32,
+    range: DateRange,
     pool: &PgPool,
-) -> Vec<CommonPlayerGame> {
+) -> Result<Vec<CommonPlayerGame>> {
     let common_game_ids = sqlx::query_scalar!(
         "SELECT game_id
         FROM game_score
         INNER JOIN game ON game.id = game_score.game_id
         WHERE player_id = ANY($1) AND game.group_id = $2
+            AND ($4::timestamp IS NULL OR game.date >= $4)
+            AND ($5::timestamp IS NULL OR game.date < $5)
         GROUP BY game_id
         HAVING COUNT(DISTINCT player_id) = $3",
         ids,
         group_id,
         ids.len() as i64,
+        range.from as _,
+        range.to as _,
     )
     .fetch_all(pool)
-    .await
-    .unwrap();
+    .await?;
 
     let common_games = sqlx::query_as!(
         CommonPlayerGame,
@@ -392,22 +662,22 @@ async fn get_common_player_games(
         INNER JOIN game
             ON game.id = game_score.game_id
         WHERE player.id = ANY($1) AND game_id = ANY($2)
-        ORDER BY date DESC"#,
+        ORDER BY date DESC, game_id DESC"#,
         &ids,
         &common_game_ids
     )
     .fetch_all(pool)
-    .await
-    .unwrap();
+    .await?;
 
-    return common_games;
+    Ok(common_games)
 }
 
 async fn get_head_to_head_stats(
     common_games: &[CommonPlayerGame],
     number_games: Option<i32>,
+    group_id: i32,
     pool: &PgPool,
-) -> Vec<PlayerStats> {
+) -> Result<Vec<PlayerStats>> {
     // Highest score for each game
     let games = sqlx::query!(
         "SELECT game.id, MAX(game_score.score) as max_score
@@ -416,11 +686,19 @@ async fn get_head_to_head_stats(
         GROUP BY game.id"
     )
     .fetch_all(pool)
-    .await
-    .unwrap();
+    .await?;
 
     let games: HashMap<_, _> = games.iter().map(|g| (g.id, g.max_score.unwrap())).collect();
 
+    // Ratings from only the games between these players, oldest first
+    let mut elo = Elo::new(get_elo_config(pool, group_id).await?);
+    let mut glicko = Glicko::new();
+    for (_, game) in &common_games.iter().rev().chunk_by(|g| g.game_id) {
+        let scores = game.map(|g| (g.player_id, g.points)).collect_vec();
+        elo.play(&scores);
+        glicko.play(&scores);
+    }
+
     // Player ID to stats
     let mut players: HashMap<i32, PlayerStats> = HashMap::new();
     for player_game in common_games {
@@ -431,6 +709,9 @@ async fn get_head_to_head_stats(
             wins: 0,
             games: 0,
             std_dev: 0.0,
+            elo: elo.rating(player_game.player_id),
+            rating: glicko.rating(player_game.player_id),
+            conservative_rating: 0.0,
         });
 
         // Skip if already got the n games
@@ -449,15 +730,16 @@ async fn get_head_to_head_stats(
         player.std_dev += player_game.points.pow(2) as f32; // Sum squared
     }
 
-    let values = players
+    let stats = players
         .into_values()
         .map(|p| PlayerStats {
-            std_dev: std_dev(p.points as f32, p.std_dev as f32, p.games),
+            std_dev: std_dev(p.points as f32, p.std_dev, p.games),
+            conservative_rating: p.rating.conservative(),
             ..p
         })
         .collect_vec();
 
-    return values;
+    Ok(stats)
 }
 
 fn get_head_to_head_histories(
@@ -492,8 +774,8 @@ fn get_head_to_head_histories(
         })
         .collect_vec();
 
-    history.sort_by(|a, b| a.id.cmp(&b.id));
-    return history;
+    history.sort_by_key(|h| h.id);
+    history
 }
 
 #[get("/group/{group_id}/head_to_head")]
@@ -501,19 +783,29 @@ pub async fn head_to_head(
     data: Data<AppState>,
     info: Query<HeadToHeadData>,
     path: Path<i32>,
-) -> impl Responder {
+    caller: MaybeCaller,
+) -> Result {
     let group_id = path.into_inner();
+    caller.require_view(data.pg_pool.as_ref(), group_id).await?;
+
     let ids: Vec<i32> = match parse_ids(&info.ids) {
         Ok(ids) => ids,
-        Err(_) => {
-            return HttpResponse::BadRequest()
-                .content_type(ContentType::plaintext())
-                .body("Could not parse ids")
-        }
+        Err(_) => return Err(Error::BadRequest("Could not parse ids".to_string())),
     };
 
-    let common_games = get_common_player_games(&ids, group_id, data.pg_pool.as_ref()).await;
-    let stats = get_head_to_head_stats(&common_games, info.n, data.pg_pool.as_ref()).await;
+    let range = get_date_range(
+        data.pg_pool.as_ref(),
+        group_id,
+        info.season_id,
+        info.from,
+        info.to,
+    )
+    .await?;
+
+    let common_games =
+        get_common_player_games(&ids, group_id, range, data.pg_pool.as_ref()).await?;
+    let stats =
+        get_head_to_head_stats(&common_games, info.n, group_id, data.pg_pool.as_ref()).await?;
     let histories = get_head_to_head_histories(&common_games, info.n);
 
     let response = HeadToHead {
@@ -521,55 +813,61 @@ pub async fn head_to_head(
         player_stats: stats,
     };
 
-    HttpResponse::Ok().json(response)
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[post("/group/{group_id}/player/{player_id}")]
 pub async fn add_player_to_group(
     data: Data<AppState>,
     path: web::Path<(i32, i32)>,
-    auth: BearerAuth,
-) -> impl Responder {
-    if !is_authorised(auth.token()).await {
-        return HttpResponse::Unauthorized()
-            .content_type(ContentType::plaintext())
-            .body("Not authorised to make this request");
-    }
-
+    caller: Caller,
+) -> Result {
     let (group_id, player_id) = path.into_inner();
+    let pool = data.pg_pool.as_ref();
+    caller
+        .require(pool, Permission::EditPlayers, &[group_id])
+        .await?;
+
+    let mut transaction = data.pg_pool.begin().await?;
     sqlx::query!(
         "INSERT INTO player_group (player_id, group_id) VALUES ($1, $2)",
         player_id,
         group_id
     )
-    .execute(data.pg_pool.as_ref())
-    .await
-    .unwrap();
+    .execute(transaction.deref_mut())
+    .await?;
+
+    let entry = Entry::new(Action::AddPlayerToGroup, player_id).group(group_id);
+    audit::record(transaction.deref_mut(), &caller, entry).await?;
+    transaction.commit().await?;
 
-    HttpResponse::NoContent().finish()
+    Ok(HttpResponse::NoContent().finish())
 }
 
 #[delete("/group/{group_id}/player/{player_id}")]
 pub async fn remove_player_from_group(
     data: Data<AppState>,
     path: web::Path<(i32, i32)>,
-    auth: BearerAuth,
-) -> impl Responder {
-    if !is_authorised(auth.token()).await {
-        return HttpResponse::Unauthorized()
-            .content_type(ContentType::plaintext())
-            .body("Not authorised to make this request");
-    }
-
+    caller: Caller,
+) -> Result {
     let (group_id, player_id) = path.into_inner();
+    let pool = data.pg_pool.as_ref();
+    caller
+        .require(pool, Permission::EditPlayers, &[group_id])
+        .await?;
+
+    let mut transaction = data.pg_pool.begin().await?;
     sqlx::query!(
         "DELETE FROM player_group WHERE player_id = $1 AND group_id = $2",
         player_id,
         group_id
     )
-    .execute(data.pg_pool.as_ref())
-    .await
-    .unwrap();
+    .execute(transaction.deref_mut())
+    .await?;
+
+    let entry = Entry::new(Action::RemovePlayerFromGroup, player_id).group(group_id);
+    audit::record(transaction.deref_mut(), &caller, entry).await?;
+    transaction.commit().await?;
 
-    HttpResponse::NoContent().finish()
+    Ok(HttpResponse::NoContent().finish())
 }
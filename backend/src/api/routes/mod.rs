@@ -1,4 +1,13 @@
+pub mod achievements;
+pub mod admins;
+pub mod api_keys;
+pub mod audit;
 pub mod auth;
+pub mod badges;
 pub mod games;
 pub mod groups;
 pub mod players;
+pub mod records;
+pub mod roles;
+pub mod seasons;
+pub mod two_factor;
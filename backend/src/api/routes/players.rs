@@ -1,25 +1,30 @@
+use std::ops::DerefMut;
+
 use actix_web::{
-    get,
-    http::header::ContentType,
-    post,
+    get, post,
     web::{self, Data, Query},
-    HttpResponse, Responder,
+    HttpResponse,
 };
-use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::NaiveDateTime;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use sqlx::{Error, PgPool};
-
-use crate::AppState;
+use sqlx::PgPool;
 
-use super::auth::is_authorised;
+use crate::{
+    access::{Caller, MaybeCaller, Permission},
+    audit::{self, Action, Entry},
+    error::{Error, Result},
+    ratings::{elo::Elo, get_elo_config, get_rated_games, glicko::GlickoRating},
+    routes::seasons::get_date_range,
+    utils::{best_window, DateRange},
+    AppState,
+};
 
 #[get("/players")]
-pub async fn list_all_players(data: Data<AppState>) -> impl Responder {
+pub async fn list_all_players(data: Data<AppState>) -> Result {
     let player = sqlx::query!("SELECT id, name FROM player")
         .fetch_all(data.pg_pool.as_ref())
-        .await
-        .unwrap();
+        .await?;
 
     let player_data = player
         .into_iter()
@@ -29,7 +34,7 @@ pub async fn list_all_players(data: Data<AppState>) -> impl Responder {
         })
         .collect_vec();
 
-    HttpResponse::Ok().json(player_data)
+    Ok(HttpResponse::Ok().json(player_data))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -41,6 +46,9 @@ pub struct PlayerStats {
     pub points: i32,
     pub games: i32,
     pub std_dev: f32,
+    pub elo: f32,
+    pub rating: GlickoRating,
+    pub conservative_rating: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -54,10 +62,19 @@ pub struct Player {
 pub struct HistoryData {
     group_id: i32,
     n: Option<usize>,
+    season_id: Option<i32>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
 }
 
-pub async fn get_player_history(pool: &PgPool, id: i32, group_id: i32, n: Option<i64>) -> Vec<i32> {
-    sqlx::query!(
+pub async fn get_player_history(
+    pool: &PgPool,
+    id: i32,
+    group_id: i32,
+    n: Option<i64>,
+    range: DateRange,
+) -> Result<Vec<i32>> {
+    let scores = sqlx::query!(
         "SELECT game_score.score
         FROM player
         INNER JOIN game_score
@@ -65,19 +82,24 @@ pub async fn get_player_history(pool: &PgPool, id: i32, group_id: i32, n: Option
         INNER JOIN game
             ON game_score.game_id = game.id
         WHERE player.id = $1 AND game.group_id = $2
+            AND ($4::timestamp IS NULL OR game.date >= $4)
+            AND ($5::timestamp IS NULL OR game.date < $5)
         ORDER BY date DESC
         LIMIT $3",
         id,
         group_id,
         n,
+        range.from as _,
+        range.to as _,
     )
     .fetch_all(pool)
-    .await
-    .unwrap()
+    .await?
     .into_iter()
     .map(|x| x.score)
     .rev()
-    .collect()
+    .collect();
+
+    Ok(scores)
 }
 
 #[get("/player/{player_id}/history")]
@@ -85,17 +107,77 @@ pub async fn player_history(
     data: Data<AppState>,
     info: Query<HistoryData>,
     path: web::Path<i32>,
-) -> impl Responder {
+    caller: MaybeCaller,
+) -> Result {
     let player_id = path.into_inner();
-    HttpResponse::Ok().json(
-        get_player_history(
-            &data.pg_pool,
-            player_id,
-            info.group_id,
-            info.n.map(|n| n as i64),
-        )
-        .await,
+    caller
+        .require_view(data.pg_pool.as_ref(), info.group_id)
+        .await?;
+
+    let range = get_date_range(
+        data.pg_pool.as_ref(),
+        info.group_id,
+        info.season_id,
+        info.from,
+        info.to,
+    )
+    .await?;
+
+    let history = get_player_history(
+        &data.pg_pool,
+        player_id,
+        info.group_id,
+        info.n.map(|n| n as i64),
+        range,
     )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(history))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RatingHistoryEntry {
+    game_id: i32,
+    date: NaiveDateTime,
+    rating: f32,
+}
+
+#[get("/player/{player_id}/rating_history")]
+pub async fn player_rating_history(
+    data: Data<AppState>,
+    info: Query<HistoryData>,
+    path: web::Path<i32>,
+    caller: MaybeCaller,
+) -> Result {
+    let player_id = path.into_inner();
+    caller
+        .require_view(data.pg_pool.as_ref(), info.group_id)
+        .await?;
+
+    let pool = data.pg_pool.as_ref();
+    let range = get_date_range(pool, info.group_id, info.season_id, info.from, info.to).await?;
+
+    let mut elo = Elo::new(get_elo_config(pool, info.group_id).await?);
+    let mut history = Vec::new();
+    for game in get_rated_games(pool, info.group_id, range).await? {
+        elo.play(&game.scores);
+
+        if game.scores.iter().any(|&(id, _)| id == player_id) {
+            history.push(RatingHistoryEntry {
+                game_id: game.id,
+                date: game.date,
+                rating: elo.rating(player_id),
+            });
+        }
+    }
+
+    // Only keep the most recent n
+    if let Some(n) = info.n {
+        history = history.split_off(history.len().saturating_sub(n));
+    }
+
+    Ok(HttpResponse::Ok().json(history))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -111,8 +193,22 @@ pub async fn player_best_streak(
     data: Data<AppState>,
     info: Query<HistoryData>,
     path: web::Path<i32>,
-) -> impl Responder {
+    caller: MaybeCaller,
+) -> Result {
     let player_id = path.into_inner();
+    caller
+        .require_view(data.pg_pool.as_ref(), info.group_id)
+        .await?;
+
+    let range = get_date_range(
+        data.pg_pool.as_ref(),
+        info.group_id,
+        info.season_id,
+        info.from,
+        info.to,
+    )
+    .await?;
+
     let scores = sqlx::query!(
         "SELECT game_score.score
         FROM player
@@ -121,34 +217,33 @@ pub async fn player_best_streak(
         INNER JOIN game
             ON game_score.game_id = game.id
         WHERE player.id = $1 AND game.group_id = $2
+            AND ($3::timestamp IS NULL OR game.date >= $3)
+            AND ($4::timestamp IS NULL OR game.date < $4)
         ORDER BY date ASC",
         player_id,
         info.group_id,
+        range.from as _,
+        range.to as _,
     )
     .fetch_all(data.pg_pool.as_ref())
-    .await
-    .unwrap()
+    .await?
     .into_iter()
     .map(|x| x.score)
     .collect::<Vec<_>>();
 
     let streak = match info.n {
         None => scores,
-        Some(n) => {
-            let windows = scores.windows(n as usize);
-            windows
-                .max_by(|w1, w2| w1.iter().sum::<i32>().cmp(&w2.iter().sum::<i32>()))
-                .map(|streak| streak.to_vec())
-                .unwrap_or(scores)
-        }
+        Some(n) => best_window(&scores, n)
+            .map(|streak| scores[streak].to_vec())
+            .unwrap_or(scores),
     };
 
-    if streak.len() == 0 {
-        return HttpResponse::Ok().json(StreakResponse {
+    if streak.is_empty() {
+        return Ok(HttpResponse::Ok().json(StreakResponse {
             scores: Vec::new(),
             avg: 0.0,
             std_dev: 0.0,
-        });
+        }));
     }
 
     let avg = streak.iter().sum::<i32>() as f32 / streak.len() as f32;
@@ -165,7 +260,7 @@ pub async fn player_best_streak(
         std_dev,
     };
 
-    HttpResponse::Ok().json(streak_resp)
+    Ok(HttpResponse::Ok().json(streak_resp))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -176,7 +271,7 @@ pub struct PlayerData {
 }
 
 #[get("/player/{player_id}")]
-pub async fn player_name(data: Data<AppState>, path: web::Path<i32>) -> impl Responder {
+pub async fn player_name(data: Data<AppState>, path: web::Path<i32>) -> Result {
     let player_id = path.into_inner();
     let player = sqlx::query!(
         r#"SELECT id, name
@@ -184,15 +279,15 @@ pub async fn player_name(data: Data<AppState>, path: web::Path<i32>) -> impl Res
         WHERE player.id = $1"#,
         player_id,
     )
-    .fetch_one(data.pg_pool.as_ref())
-    .await
-    .unwrap();
+    .fetch_optional(data.pg_pool.as_ref())
+    .await?
+    .ok_or_else(|| Error::not_found("Player not found"))?;
 
     let player_data = PlayerData {
         name: player.name,
         id: player.id,
     };
-    HttpResponse::Ok().json(player_data)
+    Ok(HttpResponse::Ok().json(player_data))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -205,40 +300,30 @@ pub struct CreatePlayerData {
 pub async fn create_player(
     data: Data<AppState>,
     payload: web::Json<CreatePlayerData>,
-    auth: BearerAuth,
-) -> impl Responder {
-    if !is_authorised(auth.token()).await {
-        return HttpResponse::Unauthorized()
-            .content_type(ContentType::plaintext())
-            .body("Not authorised to make this request");
-    }
+    caller: Caller,
+) -> Result {
+    // Players aren't in a group when they are created, so any group will do
+    let pool = data.pg_pool.as_ref();
+    caller.require(pool, Permission::EditPlayers, &[]).await?;
 
-    let player_result = sqlx::query!(
+    let mut transaction = data.pg_pool.begin().await?;
+    let player = sqlx::query!(
         r#"INSERT INTO player (name)
         VALUES ($1)
         RETURNING id, name"#,
         payload.name,
     )
-    .fetch_one(data.pg_pool.as_ref())
-    .await;
-
-    match player_result {
-        Ok(player) => {
-            let player_data = PlayerData {
-                name: player.name,
-                id: player.id,
-            };
-            return HttpResponse::Ok().json(player_data);
-        }
-        Err(Error::Database(e)) => {
-            if e.is_unique_violation() && e.constraint() == Some("name_unique") {
-                return HttpResponse::Conflict()
-                    .content_type(ContentType::plaintext())
-                    .body("Name must be unique");
-            }
-
-            panic!("{e}");
-        }
-        Err(e) => panic!("{e}"),
-    }
+    .fetch_one(transaction.deref_mut())
+    .await?;
+
+    let player_data = PlayerData {
+        name: player.name,
+        id: player.id,
+    };
+
+    let entry = Entry::new(Action::CreatePlayer, player_data.id).after(&player_data);
+    audit::record(transaction.deref_mut(), &caller, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(player_data))
 }
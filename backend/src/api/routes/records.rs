@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    get,
+    web::{self, Data, Query},
+    HttpResponse,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access::MaybeCaller,
+    error::Result,
+    ratings::{get_rated_games, RatedGame},
+    routes::{players::Player, seasons::get_date_range},
+    utils::best_window,
+    AppState,
+};
+
+const DEFAULT_AVERAGE_GAMES: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    player: Player,
+    value: f32,
+    game_id: Option<i32>,
+    date: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupRecords {
+    highest_score: Option<Record>,
+    lowest_winning_score: Option<Record>,
+    biggest_winning_margin: Option<Record>,
+    longest_win_streak: Option<Record>,
+    most_games_in_a_day: Option<Record>,
+    most_stars: Option<Record>,
+    best_average: Option<Record>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordsData {
+    n: Option<usize>, // Number of games for best average
+    season_id: Option<i32>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+}
+
+/// A possible record, before the player's name is filled in
+struct Candidate {
+    player_id: i32,
+    value: f32,
+    game_id: Option<i32>,
+    date: Option<NaiveDateTime>,
+}
+
+impl Candidate {
+    fn new(player_id: i32, value: f32, game: &RatedGame) -> Self {
+        Self {
+            player_id,
+            value,
+            game_id: Some(game.id),
+            date: Some(game.date),
+        }
+    }
+}
+
+/// Highest value wins, with the earliest kept on a tie
+fn highest(candidates: impl Iterator<Item = Candidate>) -> Option<Candidate> {
+    candidates.reduce(|best, c| if c.value > best.value { c } else { best })
+}
+
+/// Lowest value wins, with the earliest kept on a tie
+fn lowest(candidates: impl Iterator<Item = Candidate>) -> Option<Candidate> {
+    candidates.reduce(|best, c| if c.value < best.value { c } else { best })
+}
+
+fn winner_and_runner_up(game: &RatedGame) -> Option<((i32, i32), i32)> {
+    let mut scores = game.scores.iter().copied().sorted_by_key(|&(_, s)| -s);
+    let winner = scores.next()?;
+    let (_, runner_up) = scores.next()?;
+    Some((winner, runner_up))
+}
+
+fn longest_win_streak(games: &[RatedGame]) -> Option<Candidate> {
+    let mut current: HashMap<i32, usize> = HashMap::new();
+    let mut candidates = Vec::new();
+    for game in games {
+        let max = game
+            .scores
+            .iter()
+            .map(|&(_, s)| s)
+            .max()
+            .unwrap_or_default();
+        for &(player_id, score) in &game.scores {
+            let streak = current.entry(player_id).or_default();
+            if score == max {
+                *streak += 1;
+                candidates.push(Candidate::new(player_id, *streak as f32, game));
+            } else {
+                *streak = 0;
+            }
+        }
+    }
+
+    highest(candidates.into_iter())
+}
+
+fn most_games_in_a_day(games: &[RatedGame]) -> Option<Candidate> {
+    // Number of games and when the first was played
+    let mut counts: HashMap<(i32, NaiveDate), (usize, NaiveDateTime)> = HashMap::new();
+    for game in games {
+        for &(player_id, _) in &game.scores {
+            let count = counts
+                .entry((player_id, game.date.date()))
+                .or_insert((0, game.date));
+            count.0 += 1;
+        }
+    }
+
+    highest(
+        counts
+            .into_iter()
+            .sorted_by_key(|(_, (_, date))| *date)
+            .map(|((player_id, _), (count, date))| Candidate {
+                player_id,
+                value: count as f32,
+                game_id: None,
+                date: Some(date),
+            }),
+    )
+}
+
+fn most_stars(games: &[RatedGame], max_score: Option<i32>) -> Option<Candidate> {
+    let max_score = max_score?;
+    let mut stars: HashMap<i32, (usize, &RatedGame)> = HashMap::new();
+    for game in games {
+        for &(player_id, score) in &game.scores {
+            if score == max_score {
+                let entry = stars.entry(player_id).or_insert((0, game));
+                *entry = (entry.0 + 1, game);
+            }
+        }
+    }
+
+    highest(
+        stars
+            .into_iter()
+            .sorted_by_key(|(_, (_, game))| (game.date, game.id))
+            .map(|(player_id, (count, game))| Candidate::new(player_id, count as f32, game)),
+    )
+}
+
+fn best_average(games: &[RatedGame], n: usize) -> Option<Candidate> {
+    let mut histories: HashMap<i32, Vec<(i32, &RatedGame)>> = HashMap::new();
+    for game in games {
+        for &(player_id, score) in &game.scores {
+            histories.entry(player_id).or_default().push((score, game));
+        }
+    }
+
+    highest(
+        histories
+            .into_iter()
+            .filter_map(|(player_id, history)| {
+                let scores = history.iter().map(|&(score, _)| score).collect_vec();
+                let window = best_window(&scores, n)?;
+                let avg = scores[window.clone()].iter().sum::<i32>() as f32 / n as f32;
+                Some(Candidate::new(player_id, avg, history[window.end - 1].1))
+            })
+            .sorted_by_key(|c| (c.date, c.game_id)),
+    )
+}
+
+#[get("/group/{group_id}/records")]
+pub async fn group_records(
+    data: Data<AppState>,
+    info: Query<RecordsData>,
+    path: web::Path<i32>,
+    caller: MaybeCaller,
+) -> Result {
+    let group_id = path.into_inner();
+    let pool = data.pg_pool.as_ref();
+    caller.require_view(pool, group_id).await?;
+
+    let range = get_date_range(pool, group_id, info.season_id, info.from, info.to).await?;
+
+    let games = get_rated_games(pool, group_id, range).await?;
+    let max_score = sqlx::query_scalar!("SELECT max_score FROM grp WHERE id = $1", group_id)
+        .fetch_one(pool)
+        .await?;
+
+    let names: HashMap<i32, String> = sqlx::query!("SELECT id, name FROM player")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|p| (p.id, p.name))
+        .collect();
+
+    let to_record = |candidate: Option<Candidate>| {
+        candidate.map(|c| Record {
+            player: Player {
+                id: c.player_id,
+                name: names[&c.player_id].clone(),
+            },
+            value: c.value,
+            game_id: c.game_id,
+            date: c.date,
+        })
+    };
+
+    let records = GroupRecords {
+        highest_score: to_record(highest(games.iter().flat_map(|game| {
+            game.scores
+                .iter()
+                .map(move |&(player_id, score)| Candidate::new(player_id, score as f32, game))
+        }))),
+        lowest_winning_score: to_record(lowest(games.iter().filter_map(|game| {
+            let ((player_id, score), _) = winner_and_runner_up(game)?;
+            Some(Candidate::new(player_id, score as f32, game))
+        }))),
+        biggest_winning_margin: to_record(highest(games.iter().filter_map(|game| {
+            let ((player_id, score), runner_up) = winner_and_runner_up(game)?;
+            Some(Candidate::new(player_id, (score - runner_up) as f32, game))
+        }))),
+        longest_win_streak: to_record(longest_win_streak(&games)),
+        most_games_in_a_day: to_record(most_games_in_a_day(&games)),
+        most_stars: to_record(most_stars(&games, max_score)),
+        best_average: to_record(best_average(
+            &games,
+            info.n.unwrap_or(DEFAULT_AVERAGE_GAMES),
+        )),
+    };
+
+    Ok(HttpResponse::Ok().json(records))
+}
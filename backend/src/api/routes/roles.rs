@@ -0,0 +1,160 @@
+use std::ops::DerefMut;
+
+use actix_web::{
+    delete, get, put,
+    web::{self, Data},
+    HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+
+use crate::{
+    access::{Admin, Permission, Role},
+    audit::{self, Action, Entry},
+    error::{Error, Result},
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupRole {
+    user_id: i32,
+    username: String,
+    role: Role,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SetRoleData {
+    role: Role,
+}
+
+pub(crate) fn last_owner() -> Error {
+    Error::conflict("A group must have at least one owner")
+}
+
+/// Whether the admin is the only owner of the group. Locks the group's roles until the
+/// transaction ends, so two owners can't remove each other at the same time
+pub(crate) async fn is_last_owner(
+    conn: &mut PgConnection,
+    group_id: i32,
+    user_id: i32,
+) -> Result<bool> {
+    let owners = sqlx::query_scalar!(
+        "SELECT user_id FROM group_role WHERE group_id = $1 AND role = $2 FOR UPDATE",
+        group_id,
+        Role::Owner.as_str(),
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(owners == [user_id])
+}
+
+#[get("/group/{group_id}/roles")]
+pub async fn list_group_roles(data: Data<AppState>, path: web::Path<i32>, admin: Admin) -> Result {
+    let group_id = path.into_inner();
+    admin
+        .require(&data.pg_pool, Permission::ManageGroup, &[group_id])
+        .await?;
+
+    let roles: Vec<_> = sqlx::query!(
+        "SELECT group_role.user_id, admin_user.username, group_role.role
+        FROM group_role
+        JOIN admin_user ON admin_user.id = group_role.user_id
+        WHERE group_role.group_id = $1
+        ORDER BY admin_user.username",
+        group_id
+    )
+    .fetch_all(data.pg_pool.as_ref())
+    .await?
+    .into_iter()
+    .filter_map(|r| {
+        Some(GroupRole {
+            user_id: r.user_id,
+            username: r.username,
+            role: r.role.parse().ok()?,
+        })
+    })
+    .collect();
+
+    Ok(HttpResponse::Ok().json(roles))
+}
+
+/// Gives an admin a role in the group, replacing any role they already had
+#[put("/group/{group_id}/role/{user_id}")]
+pub async fn set_group_role(
+    data: Data<AppState>,
+    path: web::Path<(i32, i32)>,
+    info: web::Json<SetRoleData>,
+    admin: Admin,
+) -> Result {
+    let (group_id, user_id) = path.into_inner();
+    admin
+        .require(&data.pg_pool, Permission::ManageGroup, &[group_id])
+        .await?;
+
+    let mut transaction = data.pg_pool.begin().await?;
+
+    if info.role != Role::Owner && is_last_owner(transaction.deref_mut(), group_id, user_id).await?
+    {
+        return Err(last_owner());
+    }
+
+    sqlx::query!(
+        "INSERT INTO group_role (user_id, group_id, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, group_id) DO UPDATE SET role = EXCLUDED.role",
+        user_id,
+        group_id,
+        info.role.as_str(),
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+
+    let entry = Entry::new(Action::SetGroupRole, user_id)
+        .group(group_id)
+        .after(&*info);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/group/{group_id}/role/{user_id}")]
+pub async fn remove_group_role(
+    data: Data<AppState>,
+    path: web::Path<(i32, i32)>,
+    admin: Admin,
+) -> Result {
+    let (group_id, user_id) = path.into_inner();
+    admin
+        .require(&data.pg_pool, Permission::ManageGroup, &[group_id])
+        .await?;
+
+    let mut transaction = data.pg_pool.begin().await?;
+
+    if is_last_owner(transaction.deref_mut(), group_id, user_id).await? {
+        return Err(last_owner());
+    }
+
+    let role = sqlx::query_scalar!(
+        "DELETE FROM group_role WHERE group_id = $1 AND user_id = $2 RETURNING role",
+        group_id,
+        user_id
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?;
+
+    let Some(role) = role else {
+        return Err(Error::not_found("Admin does not have a role in this group"));
+    };
+
+    let entry = Entry::new(Action::RemoveGroupRole, user_id)
+        .group(group_id)
+        .before(&serde_json::json!({ "role": role }));
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
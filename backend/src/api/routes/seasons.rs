@@ -0,0 +1,366 @@
+use std::ops::DerefMut;
+
+use actix_web::{
+    get, post,
+    web::{self, Data},
+    HttpResponse,
+};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgExecutor, PgConnection, PgPool};
+
+use crate::{
+    access::{Admin, MaybeCaller, Permission},
+    audit::{self, Action, Entry},
+    error::{Error, Result},
+    routes::{groups::get_stats, players::Player},
+    utils::DateRange,
+    AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Season {
+    id: i32,
+    group_id: i32,
+    name: String,
+    start_date: NaiveDateTime,
+    end_date: Option<NaiveDateTime>,
+    closed: bool,
+    champion: Option<Player>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SeasonStanding {
+    position: i32,
+    player_id: i32,
+    name: String,
+    wins: i32,
+    points: i32,
+    games: i32,
+    rating: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SeasonDetails {
+    #[serde(flatten)]
+    season: Season,
+    standings: Vec<SeasonStanding>,
+}
+
+/// Date range to get stats for, narrowed to the season if one is given. The season must be in the
+/// group, so it can't be used to see the dates of another group's seasons
+pub async fn get_date_range(
+    pool: &PgPool,
+    group_id: i32,
+    season_id: Option<i32>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+) -> Result<DateRange> {
+    let range = DateRange { from, to };
+    let Some(season_id) = season_id else {
+        return Ok(range);
+    };
+
+    let season = sqlx::query!(
+        r#"SELECT
+            start_date as "start_date: NaiveDateTime",
+            end_date as "end_date: NaiveDateTime"
+        FROM season
+        WHERE id = $1 AND group_id = $2"#,
+        season_id,
+        group_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    match season {
+        Some(season) => Ok(range.intersect(DateRange {
+            from: Some(season.start_date),
+            to: season.end_date,
+        })),
+        None => Err(Error::not_found("Season not found")),
+    }
+}
+
+async fn fetch_seasons<'e>(
+    executor: impl PgExecutor<'e>,
+    group_id: Option<i32>,
+    id: Option<i32>,
+) -> Result<Vec<Season>> {
+    let seasons = sqlx::query!(
+        r#"SELECT
+            season.id,
+            season.group_id,
+            season.name,
+            season.start_date as "start_date: NaiveDateTime",
+            season.end_date as "end_date: NaiveDateTime",
+            season.closed,
+            season.champion_id,
+            player.name as "champion_name?"
+        FROM season
+        LEFT JOIN player ON player.id = season.champion_id
+        WHERE ($1::int IS NULL OR season.group_id = $1)
+            AND ($2::int IS NULL OR season.id = $2)
+        ORDER BY season.start_date DESC"#,
+        group_id,
+        id,
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|s| Season {
+        id: s.id,
+        group_id: s.group_id,
+        name: s.name,
+        start_date: s.start_date,
+        end_date: s.end_date,
+        closed: s.closed,
+        champion: s
+            .champion_id
+            .zip(s.champion_name)
+            .map(|(id, name)| Player { id, name }),
+    })
+    .collect();
+
+    Ok(seasons)
+}
+
+#[get("/group/{group_id}/seasons")]
+pub async fn list_seasons(
+    data: Data<AppState>,
+    path: web::Path<i32>,
+    caller: MaybeCaller,
+) -> Result {
+    let group_id = path.into_inner();
+    caller.require_view(data.pg_pool.as_ref(), group_id).await?;
+
+    let seasons = fetch_seasons(data.pg_pool.as_ref(), Some(group_id), None).await?;
+    Ok(HttpResponse::Ok().json(seasons))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateSeasonData {
+    name: String,
+    start_date: Option<NaiveDateTime>,
+    end_date: Option<NaiveDateTime>,
+}
+
+#[post("/group/{group_id}/season")]
+pub async fn create_season(
+    data: Data<AppState>,
+    path: web::Path<i32>,
+    payload: web::Json<CreateSeasonData>,
+    admin: Admin,
+) -> Result {
+    let group_id = path.into_inner();
+    let pool = data.pg_pool.as_ref();
+    admin
+        .require(pool, Permission::ManageGroup, &[group_id])
+        .await?;
+
+    let start_date = payload.start_date.unwrap_or(Utc::now().naive_utc());
+    if payload.end_date.is_some_and(|end| end <= start_date) {
+        return Err(Error::validation(
+            "endDate",
+            "Season must end after it starts",
+        ));
+    }
+
+    let mut transaction = data.pg_pool.begin().await?;
+    let season_id = sqlx::query_scalar!(
+        "INSERT INTO season (group_id, name, start_date, end_date)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id",
+        group_id,
+        payload.name,
+        start_date as _,
+        payload.end_date as _,
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+
+    let season = fetch_seasons(transaction.deref_mut(), None, Some(season_id)).await?;
+    let entry = Entry::new(Action::CreateSeason, season_id)
+        .group(group_id)
+        .after(&season[0]);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(&season[0]))
+}
+
+#[get("/season/{season_id}")]
+pub async fn get_season(data: Data<AppState>, path: web::Path<i32>, caller: MaybeCaller) -> Result {
+    let season_id = path.into_inner();
+    let Some(season) = fetch_seasons(data.pg_pool.as_ref(), None, Some(season_id))
+        .await?
+        .pop()
+    else {
+        return Err(Error::not_found("Season not found"));
+    };
+
+    caller
+        .require_view(data.pg_pool.as_ref(), season.group_id)
+        .await?;
+
+    let standings = sqlx::query_as!(
+        SeasonStanding,
+        "SELECT position, player_id, player.name, wins, points, games, rating
+        FROM season_standing
+        INNER JOIN player ON player.id = season_standing.player_id
+        WHERE season_id = $1
+        ORDER BY position ASC",
+        season_id
+    )
+    .fetch_all(data.pg_pool.as_ref())
+    .await?;
+
+    Ok(HttpResponse::Ok().json(SeasonDetails { season, standings }))
+}
+
+/// Records the final standings of the season and crowns the player at the top as champion.
+/// Locks the season until the transaction ends, and returns false if it was already closed
+async fn close(
+    pool: &PgPool,
+    conn: &mut PgConnection,
+    season: &Season,
+    end_date: NaiveDateTime,
+) -> Result<bool> {
+    let closed = sqlx::query_scalar!(
+        "SELECT closed FROM season WHERE id = $1 FOR UPDATE",
+        season.id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if closed {
+        return Ok(false);
+    }
+
+    let range = DateRange {
+        from: Some(season.start_date),
+        to: Some(end_date),
+    };
+
+    let stats = get_stats(pool, season.group_id, None, false, range).await?;
+
+    for (i, player) in stats.iter().enumerate() {
+        sqlx::query!(
+            "INSERT INTO season_standing (season_id, player_id, position, wins, points, games, rating)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            season.id,
+            player.id,
+            i as i32 + 1,
+            player.wins,
+            player.points,
+            player.games,
+            player.conservative_rating,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query!(
+        "UPDATE season SET closed = true, end_date = $2, champion_id = $3
+        WHERE id = $1 AND NOT closed",
+        season.id,
+        end_date as _,
+        stats.first().map(|p| p.id),
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(true)
+}
+
+#[post("/season/{season_id}/close")]
+pub async fn close_season(data: Data<AppState>, path: web::Path<i32>, admin: Admin) -> Result {
+    let season_id = path.into_inner();
+    let Some(season) = fetch_seasons(data.pg_pool.as_ref(), None, Some(season_id))
+        .await?
+        .pop()
+    else {
+        return Err(Error::not_found("Season not found"));
+    };
+
+    let pool = data.pg_pool.as_ref();
+    admin
+        .require(pool, Permission::ManageGroup, &[season.group_id])
+        .await?;
+
+    // Closing early cuts the season short
+    let now = Utc::now().naive_utc();
+    let end_date = season.end_date.map_or(now, |end| end.min(now));
+
+    let mut transaction = data.pg_pool.begin().await?;
+    if !close(pool, transaction.deref_mut(), &season, end_date).await? {
+        return Err(Error::conflict("Season is already closed"));
+    }
+
+    let closed = fetch_seasons(transaction.deref_mut(), None, Some(season_id)).await?;
+    let entry = Entry::new(Action::CloseSeason, season_id)
+        .group(season.group_id)
+        .before(&season)
+        .after(&closed[0]);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(&closed[0]))
+}
+
+/// Closes the group's season if it has ended, and starts the next one with the same length. The
+/// season is locked while this happens, so concurrent rollovers don't close it twice
+pub async fn rollover_seasons(pool: &PgPool, group_id: i32) -> Result<()> {
+    let now = Utc::now().naive_utc();
+
+    // Loop in case several seasons have passed since the last game
+    loop {
+        let Some(season) = fetch_seasons(pool, Some(group_id), None)
+            .await?
+            .into_iter()
+            .find(|s| !s.closed)
+        else {
+            return Ok(());
+        };
+
+        let Some(end_date) = season.end_date.filter(|&end| end <= now) else {
+            return Ok(());
+        };
+
+        let mut transaction = pool.begin().await?;
+
+        // Already rolled over by another request, so look for the season it started
+        if !close(pool, transaction.deref_mut(), &season, end_date).await? {
+            continue;
+        }
+
+        let length = end_date - season.start_date;
+        if length <= TimeDelta::zero() {
+            transaction.commit().await?;
+            return Ok(());
+        }
+
+        let season_count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM season WHERE group_id = $1"#,
+            group_id
+        )
+        .fetch_one(transaction.deref_mut())
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO season (group_id, name, start_date, end_date) VALUES ($1, $2, $3, $4)",
+            group_id,
+            format!("Season {}", season_count + 1),
+            end_date as _,
+            (end_date + length) as _,
+        )
+        .execute(transaction.deref_mut())
+        .await?;
+
+        transaction.commit().await?;
+    }
+}
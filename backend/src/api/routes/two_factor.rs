@@ -0,0 +1,338 @@
+use std::ops::DerefMut;
+
+use actix_web::{
+    delete, post,
+    web::{self, Data},
+    HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+
+use crate::{
+    access::Admin,
+    audit::{self, Action, Entry},
+    error::{Error, Result},
+    two_factor::{
+        encode_secret, generate_recovery_codes, generate_secret, hash_recovery_code, otpauth_url,
+        verify_totp,
+    },
+    AppState,
+};
+
+use super::auth::{client_ip, start_session, two_factor_user};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Enrolment {
+    secret: String,
+    otpauth_url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeData {
+    code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyData {
+    two_factor_token: String,
+    code: String,
+}
+
+/// Checks a TOTP code or an unused recovery code for the admin. Either is marked as used if
+/// valid, so it can't be used again
+async fn check_code(conn: &mut PgConnection, user_id: i32, code: &str) -> Result<bool> {
+    let admin_user = sqlx::query!(
+        "SELECT totp_secret, totp_last_step FROM admin_user WHERE id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let Some(secret) = admin_user.totp_secret else {
+        return Ok(false);
+    };
+
+    let now = Utc::now().timestamp();
+    if let Some(step) = verify_totp(&secret, code.trim(), now, admin_user.totp_last_step) {
+        sqlx::query!(
+            "UPDATE admin_user SET totp_last_step = $2 WHERE id = $1",
+            user_id,
+            step
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        return Ok(true);
+    }
+
+    let used = sqlx::query!(
+        "UPDATE admin_recovery_code
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+        user_id,
+        hash_recovery_code(code),
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(used.rows_affected() > 0)
+}
+
+/// Starts enrolment by generating a secret for the admin to add to their authenticator app. It
+/// isn't required to log in until confirmed
+#[post("/auth/2fa")]
+pub async fn start_two_factor_enrolment(data: Data<AppState>, admin: Admin) -> Result {
+    let secret = generate_secret();
+    let updated = sqlx::query!(
+        "UPDATE admin_user
+        SET totp_secret = $2, totp_last_step = NULL
+        WHERE id = $1 AND NOT totp_enabled",
+        admin.id,
+        secret,
+    )
+    .execute(data.pg_pool.as_ref())
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return Err(Error::conflict(
+            "Two-factor authentication is already enabled",
+        ));
+    }
+
+    let resp = Enrolment {
+        secret: encode_secret(&secret),
+        otpauth_url: otpauth_url(&admin.username, &secret),
+    };
+
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+/// Finishes enrolment once the admin has entered a valid code from their app. Responds with the
+/// recovery codes, which are only ever shown this once
+#[post("/auth/2fa/confirm")]
+pub async fn confirm_two_factor_enrolment(
+    data: Data<AppState>,
+    info: web::Json<CodeData>,
+    admin: Admin,
+) -> Result {
+    let mut transaction = data.pg_pool.begin().await?;
+
+    let admin_user = sqlx::query!(
+        "SELECT id, totp_enabled, totp_secret IS NOT NULL as \"started!\"
+        FROM admin_user
+        WHERE id = $1
+        FOR UPDATE",
+        admin.id
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+
+    if admin_user.totp_enabled {
+        return Err(Error::conflict(
+            "Two-factor authentication is already enabled",
+        ));
+    }
+
+    if !admin_user.started {
+        return Err(Error::conflict(
+            "Two-factor authentication enrolment has not been started",
+        ));
+    }
+
+    if !check_code(transaction.deref_mut(), admin_user.id, &info.code).await? {
+        return Err(Error::validation("code", "Invalid code"));
+    }
+
+    sqlx::query!(
+        "UPDATE admin_user SET totp_enabled = true WHERE id = $1",
+        admin_user.id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+
+    let recovery_codes = generate_recovery_codes();
+    let hashes = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect::<Vec<_>>();
+
+    sqlx::query!(
+        "DELETE FROM admin_recovery_code WHERE user_id = $1",
+        admin_user.id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO admin_recovery_code (user_id, code_hash)
+        SELECT $1, * FROM UNNEST($2::text[])",
+        admin_user.id,
+        &hashes,
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+
+    let entry = Entry::new(Action::EnableTwoFactor, admin.id);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes }))
+}
+
+/// Turns off two-factor authentication. Needs a valid code so a stolen session can't remove it
+#[delete("/auth/2fa")]
+pub async fn disable_two_factor(
+    data: Data<AppState>,
+    info: web::Json<CodeData>,
+    admin: Admin,
+    req: HttpRequest,
+) -> Result {
+    let ip = client_ip(&req);
+    if let Some(retry_after) = data
+        .login_limiter
+        .retry_after(&admin.username, ip.as_deref())
+    {
+        return Err(Error::TooManyRequests(retry_after));
+    }
+
+    let mut transaction = data.pg_pool.begin().await?;
+
+    let admin_user = sqlx::query!(
+        "SELECT id, totp_enabled FROM admin_user WHERE id = $1",
+        admin.id
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+
+    if !admin_user.totp_enabled {
+        return Err(Error::conflict("Two-factor authentication is not enabled"));
+    }
+
+    if !check_code(transaction.deref_mut(), admin_user.id, &info.code).await? {
+        data.login_limiter
+            .record_failure(&admin.username, ip.as_deref());
+        return Err(Error::validation("code", "Invalid code"));
+    }
+
+    sqlx::query!(
+        "UPDATE admin_user
+        SET totp_enabled = false, totp_secret = NULL, totp_last_step = NULL
+        WHERE id = $1",
+        admin_user.id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM admin_recovery_code WHERE user_id = $1",
+        admin_user.id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+
+    let entry = Entry::new(Action::DisableTwoFactor, admin.id);
+    audit::record(transaction.deref_mut(), &admin, entry).await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Second step of logging in. Swaps the token from `/auth` and a valid code for the real tokens
+#[post("/auth/2fa/verify")]
+pub async fn verify_two_factor(
+    data: Data<AppState>,
+    info: web::Json<VerifyData>,
+    req: HttpRequest,
+) -> Result {
+    let Some(user_id) = two_factor_user(&data.jwt_keys, &info.two_factor_token) else {
+        return Err(Error::unauthorised("Invalid two-factor token"));
+    };
+
+    let username = sqlx::query_scalar!("SELECT username FROM admin_user WHERE id = $1", user_id)
+        .fetch_optional(data.pg_pool.as_ref())
+        .await?;
+
+    // Admin was deleted after entering their password
+    let Some(username) = username else {
+        return Err(Error::unauthorised("Invalid two-factor token"));
+    };
+
+    let ip = client_ip(&req);
+    if let Some(retry_after) = data.login_limiter.retry_after(&username, ip.as_deref()) {
+        return Err(Error::TooManyRequests(retry_after));
+    }
+
+    let mut transaction = data.pg_pool.begin().await?;
+    if !check_code(transaction.deref_mut(), user_id, &info.code).await? {
+        log::warn!(
+            "Failed two-factor code for user {username:?} from {}",
+            ip.as_deref().unwrap_or("unknown IP")
+        );
+        data.login_limiter.record_failure(&username, ip.as_deref());
+
+        return Err(Error::unauthorised("Invalid code"));
+    }
+
+    transaction.commit().await?;
+    data.login_limiter.record_success(&username);
+
+    let resp = start_session(&data, user_id, &username, &req).await?;
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    async fn create_admin(pool: &PgPool) -> i32 {
+        sqlx::query_scalar!(
+            "INSERT INTO admin_user (username, password_hash, totp_secret, totp_enabled)
+            VALUES ('test', '', $1, true)
+            RETURNING id",
+            generate_secret(),
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn recovery_codes_can_only_be_used_once(pool: PgPool) {
+        let user_id = create_admin(&pool).await;
+        sqlx::query!(
+            "INSERT INTO admin_recovery_code (user_id, code_hash) VALUES ($1, $2)",
+            user_id,
+            hash_recovery_code("abcde-fghjk"),
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let mut conn = pool.acquire().await.unwrap();
+        assert!(check_code(&mut conn, user_id, " ABCDE-FGHJK ")
+            .await
+            .unwrap());
+        assert!(!check_code(&mut conn, user_id, "abcde-fghjk").await.unwrap());
+        assert!(!check_code(&mut conn, user_id, "abcdefghjk").await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn rejects_unknown_codes(pool: PgPool) {
+        let user_id = create_admin(&pool).await;
+
+        let mut conn = pool.acquire().await.unwrap();
+        assert!(!check_code(&mut conn, user_id, "abcde-fghjk").await.unwrap());
+    }
+}
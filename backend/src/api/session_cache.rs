@@ -0,0 +1,38 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+/// How long a session is trusted before checking the database again
+const SESSION_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Sessions recently confirmed to exist, so access tokens don't need a database hit on every
+/// request. Sessions must be revoked here as well as in the database.
+#[derive(Debug, Default)]
+pub struct SessionCache {
+    checked_at: Mutex<HashMap<Uuid, Instant>>,
+}
+
+impl SessionCache {
+    pub fn is_valid(&self, sid: Uuid) -> bool {
+        let checked_at = self.checked_at.lock().unwrap();
+        checked_at
+            .get(&sid)
+            .is_some_and(|t| t.elapsed() < SESSION_CACHE_TTL)
+    }
+
+    pub fn insert(&self, sid: Uuid) {
+        let mut checked_at = self.checked_at.lock().unwrap();
+
+        // Stop expired sessions building up
+        checked_at.retain(|_, t| t.elapsed() < SESSION_CACHE_TTL);
+        checked_at.insert(sid, Instant::now());
+    }
+
+    pub fn revoke(&self, sid: Uuid) {
+        self.checked_at.lock().unwrap().remove(&sid);
+    }
+}
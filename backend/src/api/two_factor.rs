@@ -0,0 +1,203 @@
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{seq::SliceRandom, RngCore};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Name shown next to the code in authenticator apps
+const ISSUER: &str = "Mario Kart Scoreboard";
+
+const SECRET_SIZE: usize = 20;
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+
+/// Number of steps either side of the current one that are accepted, to allow for clock drift
+const ALLOWED_SKEW: i64 = 1;
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Characters used in recovery codes. Excludes ones that are easily confused (0/o, 1/l etc.)
+const RECOVERY_CODE_CHARS: &[u8] = b"23456789abcdefghjkmnpqrstuvwxyz";
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0; SECRET_SIZE];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// Base32 encoded secret, for entering into an authenticator app manually
+pub fn encode_secret(secret: &[u8]) -> String {
+    BASE32_NOPAD.encode(secret)
+}
+
+/// URL for the QR code scanned by authenticator apps
+pub fn otpauth_url(username: &str, secret: &[u8]) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&digits={DIGITS}&period={STEP_SECS}",
+        urlencoding::encode(ISSUER),
+        urlencoding::encode(username),
+        encode_secret(secret),
+        urlencoding::encode(ISSUER),
+    )
+}
+
+/// HOTP value (RFC 4226) for the counter, before it is cut down to the number of digits
+fn hotp_value(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).unwrap();
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff
+}
+
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    hotp_value(secret, counter) % 10u32.pow(DIGITS)
+}
+
+/// Checks a TOTP code (RFC 6238) at the given unix time, returning the time step it was valid
+/// for. Steps at or before `last_step` are rejected so a code can't be used twice
+pub fn verify_totp(secret: &[u8], code: &str, now: i64, last_step: Option<i64>) -> Option<i64> {
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let code: u32 = code.parse().ok()?;
+    let current_step = now / STEP_SECS;
+
+    ((current_step - ALLOWED_SKEW)..=(current_step + ALLOWED_SKEW))
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| hotp(secret, *step as u64) == code)
+}
+
+/// Single use codes for logging in without the authenticator app. Formatted as `xxxxx-xxxxx`
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut chars = (0..10)
+                .map(|_| *RECOVERY_CODE_CHARS.choose(&mut rng).unwrap() as char)
+                .collect::<String>();
+            chars.insert(5, '-');
+            chars
+        })
+        .collect()
+}
+
+/// Recovery codes are random enough that a fast hash is fine (unlike passwords)
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalised = code.trim().to_lowercase().replace('-', "");
+    let hash = Sha256::digest(normalised.as_bytes());
+    data_encoding::HEXLOWER.encode(&hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secret used by the RFC 6238 test vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    /// (Unix time, 8 digit code) from RFC 6238 appendix B, for SHA-1
+    const RFC_VECTORS: &[(i64, u32)] = &[
+        (59, 94287082),
+        (1111111109, 7081804),
+        (1111111111, 14050471),
+        (1234567890, 89005924),
+        (2000000000, 69279037),
+        (20000000000, 65353130),
+    ];
+
+    fn code_at(now: i64) -> String {
+        format!("{:06}", hotp(RFC_SECRET, (now / STEP_SECS) as u64))
+    }
+
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        for &(now, expected) in RFC_VECTORS {
+            let step = now / STEP_SECS;
+            assert_eq!(hotp_value(RFC_SECRET, step as u64) % 100_000_000, expected);
+
+            let code = format!("{:06}", expected % 1_000_000);
+            assert_eq!(verify_totp(RFC_SECRET, &code, now, None), Some(step));
+        }
+    }
+
+    #[test]
+    fn six_digit_code_at_59_seconds() {
+        assert_eq!(verify_totp(RFC_SECRET, "287082", 59, None), Some(1));
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        assert_eq!(verify_totp(RFC_SECRET, "28708", 59, None), None);
+        assert_eq!(verify_totp(RFC_SECRET, "2870820", 59, None), None);
+        assert_eq!(verify_totp(RFC_SECRET, "28708a", 59, None), None);
+        assert_eq!(verify_totp(RFC_SECRET, "+28708", 59, None), None);
+        assert_eq!(verify_totp(RFC_SECRET, "", 59, None), None);
+    }
+
+    #[test]
+    fn accepts_one_step_either_side() {
+        let now = 1234567890;
+        let step = now / STEP_SECS;
+
+        let previous = code_at(now - STEP_SECS);
+        let next = code_at(now + STEP_SECS);
+        assert_eq!(
+            verify_totp(RFC_SECRET, &previous, now, None),
+            Some(step - 1)
+        );
+        assert_eq!(verify_totp(RFC_SECRET, &next, now, None), Some(step + 1));
+
+        let too_old = code_at(now - 2 * STEP_SECS);
+        let too_new = code_at(now + 2 * STEP_SECS);
+        assert_eq!(verify_totp(RFC_SECRET, &too_old, now, None), None);
+        assert_eq!(verify_totp(RFC_SECRET, &too_new, now, None), None);
+    }
+
+    #[test]
+    fn rejects_replayed_steps() {
+        let now = 1234567890;
+        let step = now / STEP_SECS;
+        let code = code_at(now);
+
+        assert_eq!(
+            verify_totp(RFC_SECRET, &code, now, Some(step - 1)),
+            Some(step)
+        );
+        assert_eq!(verify_totp(RFC_SECRET, &code, now, Some(step)), None);
+        assert_eq!(verify_totp(RFC_SECRET, &code, now, Some(step + 1)), None);
+
+        // A code from the previous step can't be used once a later one has been
+        let previous = code_at(now - STEP_SECS);
+        assert_eq!(verify_totp(RFC_SECRET, &previous, now, Some(step)), None);
+    }
+
+    #[test]
+    fn recovery_codes_are_formatted_and_unique() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+
+        for code in &codes {
+            let (first, second) = code.split_once('-').unwrap();
+            assert_eq!((first.len(), second.len()), (5, 5));
+            assert!(code
+                .bytes()
+                .filter(|c| *c != b'-')
+                .all(|c| RECOVERY_CODE_CHARS.contains(&c)));
+        }
+
+        let unique: std::collections::HashSet<_> = codes.iter().collect();
+        assert_eq!(unique.len(), codes.len());
+    }
+
+    #[test]
+    fn recovery_code_hash_ignores_case_spaces_and_dashes() {
+        let hash = hash_recovery_code("abcde-fghjk");
+        assert_eq!(hash_recovery_code("abcdefghjk"), hash);
+        assert_eq!(hash_recovery_code(" ABCDE-FGHJK\n"), hash);
+        assert_ne!(hash_recovery_code("abcde-fghjm"), hash);
+    }
+}
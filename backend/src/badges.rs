@@ -0,0 +1,12 @@
+//! Badge tiers shared by the API and the tools
+
+/// Tiers given to new groups as (name, emoji, fraction of the max score), matching the original
+/// star, gold, silver and bronze badges. The migration that added badge tiers gave existing groups
+/// the same tiers, but it can't use this constant and applied migrations mustn't change, so update
+/// existing groups with a new migration if these change
+pub const DEFAULT_BADGE_TIERS: &[(&str, &str, f32)] = &[
+    ("star", "🎖️", 1.0),
+    ("gold", "🥇", 0.94),
+    ("silver", "🥈", 0.88),
+    ("bronze", "🥉", 0.83),
+];
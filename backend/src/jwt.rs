@@ -0,0 +1,9 @@
+//! Layout of the JWT key directory, shared by the API and `tools rotate-jwt-key`
+
+/// Extension of the key files. The file name (without extension) is the key ID that gets put in
+/// the `kid` header of tokens
+pub const KEY_EXTENSION: &str = "key";
+
+/// Key ID of the old `jwt_secret.dat`, copied to `legacy.key`. It is only used to verify tokens
+/// issued before keys had IDs, and is never used to sign or deleted by rotation
+pub const LEGACY_KID: &str = "legacy";
@@ -0,0 +1,7 @@
+//! Code shared by the API and the tools
+
+pub mod badges;
+pub mod jwt;
+pub mod passwords;
+pub mod ratings;
+pub mod utils;
@@ -0,0 +1,19 @@
+//! Password policy for admins, checked by both the API and `tools create-admin`
+
+pub const MIN_PASSWORD_LENGTH: usize = 12;
+
+/// Returns why the password isn't allowed for the admin, or nothing if it is
+pub fn policy_errors(username: &str, password: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        errors.push(format!(
+            "Password must be at least {MIN_PASSWORD_LENGTH} characters"
+        ));
+    }
+
+    if password.eq_ignore_ascii_case(username) {
+        errors.push("Password cannot be the username".to_string());
+    }
+
+    errors
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+pub const DEFAULT_K_FACTOR: f32 = 32.0;
+pub const DEFAULT_INITIAL_RATING: f32 = 1500.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct EloConfig {
+    pub k_factor: f32,
+    pub initial_rating: f32,
+}
+
+/// Multiplayer Elo, where each game is treated as a set of head-to-head results between every
+/// pair of players
+#[derive(Debug, Clone)]
+pub struct Elo {
+    config: EloConfig,
+    ratings: HashMap<i32, f32>,
+}
+
+impl Elo {
+    pub fn new(config: EloConfig) -> Self {
+        Self {
+            config,
+            ratings: HashMap::new(),
+        }
+    }
+
+    pub fn rating(&self, player_id: i32) -> f32 {
+        *self
+            .ratings
+            .get(&player_id)
+            .unwrap_or(&self.config.initial_rating)
+    }
+
+    /// Updates ratings from a game, given as (player ID, score) pairs
+    pub fn play(&mut self, scores: &[(i32, i32)]) {
+        if scores.len() < 2 {
+            return;
+        }
+
+        // K is split between opponents so a big lobby doesn't swing ratings more than a 1v1
+        let k = self.config.k_factor / (scores.len() - 1) as f32;
+
+        let deltas = scores
+            .iter()
+            .map(|&(id, score)| {
+                let rating = self.rating(id);
+                let delta: f32 = scores
+                    .iter()
+                    .filter(|&&(other_id, _)| other_id != id)
+                    .map(|&(other_id, other_score)| {
+                        let expected =
+                            1.0 / (1.0 + 10f32.powf((self.rating(other_id) - rating) / 400.0));
+                        let actual = match score.cmp(&other_score) {
+                            std::cmp::Ordering::Greater => 1.0,
+                            std::cmp::Ordering::Equal => 0.5,
+                            std::cmp::Ordering::Less => 0.0,
+                        };
+
+                        actual - expected
+                    })
+                    .sum();
+
+                (id, rating + k * delta)
+            })
+            .collect::<Vec<_>>();
+
+        self.ratings.extend(deltas);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elo() -> Elo {
+        Elo::new(EloConfig {
+            k_factor: DEFAULT_K_FACTOR,
+            initial_rating: DEFAULT_INITIAL_RATING,
+        })
+    }
+
+    fn total(elo: &Elo, ids: &[i32]) -> f32 {
+        ids.iter().map(|id| elo.rating(*id)).sum()
+    }
+
+    #[test]
+    fn unrated_players_start_at_initial_rating() {
+        assert_eq!(elo().rating(1), DEFAULT_INITIAL_RATING);
+    }
+
+    #[test]
+    fn head_to_head_is_symmetric() {
+        let mut elo = elo();
+        elo.play(&[(1, 15), (2, 9)]);
+
+        assert_eq!(
+            elo.rating(1),
+            DEFAULT_INITIAL_RATING + DEFAULT_K_FACTOR / 2.0
+        );
+        assert_eq!(
+            elo.rating(2),
+            DEFAULT_INITIAL_RATING - DEFAULT_K_FACTOR / 2.0
+        );
+    }
+
+    #[test]
+    fn tie_between_equal_players_changes_nothing() {
+        let mut elo = elo();
+        elo.play(&[(1, 12), (2, 12)]);
+
+        assert_eq!(elo.rating(1), DEFAULT_INITIAL_RATING);
+        assert_eq!(elo.rating(2), DEFAULT_INITIAL_RATING);
+    }
+
+    #[test]
+    fn upset_moves_ratings_more() {
+        let mut elo = elo();
+        elo.play(&[(1, 15), (2, 9)]);
+        let favourite_gain = elo.rating(1) - DEFAULT_INITIAL_RATING;
+
+        // The underdog beating the favourite gains more than the favourite did
+        let before = elo.rating(2);
+        elo.play(&[(1, 9), (2, 15)]);
+        assert!(elo.rating(2) - before > favourite_gain);
+        assert!((total(&elo, &[1, 2]) - 2.0 * DEFAULT_INITIAL_RATING).abs() < 1e-3);
+    }
+
+    #[test]
+    fn multiplayer_splits_k_between_opponents() {
+        let mut elo = elo();
+        elo.play(&[(1, 15), (2, 12), (3, 12), (4, 3)]);
+
+        // Beat all 3 opponents, so gains the same as winning a 1v1
+        let k = DEFAULT_K_FACTOR / 3.0;
+        assert!((elo.rating(1) - (DEFAULT_INITIAL_RATING + k * 1.5)).abs() < 1e-3);
+        assert!((elo.rating(2) - DEFAULT_INITIAL_RATING).abs() < 1e-3);
+        assert_eq!(elo.rating(2), elo.rating(3));
+        assert!((elo.rating(4) - (DEFAULT_INITIAL_RATING - k * 1.5)).abs() < 1e-3);
+        assert!((total(&elo, &[1, 2, 3, 4]) - 4.0 * DEFAULT_INITIAL_RATING).abs() < 1e-3);
+    }
+
+    #[test]
+    fn ignores_games_with_one_player() {
+        let mut elo = elo();
+        elo.play(&[(1, 15)]);
+        assert!(elo.ratings.is_empty());
+    }
+}
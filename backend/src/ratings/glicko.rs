@@ -0,0 +1,261 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use serde::{Deserialize, Serialize};
+
+/// Converts between the Glicko and Glicko-2 scales
+const SCALE: f64 = 173.7178;
+const INITIAL_RATING: f64 = 1500.0;
+const INITIAL_DEVIATION: f64 = 350.0;
+const INITIAL_VOLATILITY: f64 = 0.06;
+
+/// Constrains how much volatility can change between games
+const TAU: f64 = 0.5;
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GlickoRating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for GlickoRating {
+    fn default() -> Self {
+        Self {
+            rating: INITIAL_RATING,
+            deviation: INITIAL_DEVIATION,
+            volatility: INITIAL_VOLATILITY,
+        }
+    }
+}
+
+impl GlickoRating {
+    /// A rating we are ~95% sure the player is above
+    pub fn conservative(&self) -> f64 {
+        self.rating - 2.0 * self.deviation
+    }
+
+    fn mu(&self) -> f64 {
+        (self.rating - INITIAL_RATING) / SCALE
+    }
+
+    fn phi(&self) -> f64 {
+        self.deviation / SCALE
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi.powi(2) / PI.powi(2)).sqrt()
+}
+
+fn expected(mu: f64, mu_j: f64, phi_j: f64) -> f64 {
+    1.0 / (1.0 + (-g(phi_j) * (mu - mu_j)).exp())
+}
+
+/// Glicko-2, where each game is a rating period made up of head-to-head results between every
+/// pair of players
+#[derive(Debug, Clone, Default)]
+pub struct Glicko {
+    ratings: HashMap<i32, GlickoRating>,
+}
+
+impl Glicko {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rating(&self, player_id: i32) -> GlickoRating {
+        self.ratings.get(&player_id).copied().unwrap_or_default()
+    }
+
+    pub fn ratings(&self) -> &HashMap<i32, GlickoRating> {
+        &self.ratings
+    }
+
+    /// Updates ratings from a game, given as (player ID, score) pairs
+    pub fn play(&mut self, scores: &[(i32, i32)]) {
+        if scores.len() < 2 {
+            return;
+        }
+
+        // Players that sat this game out become less certain
+        for (id, rating) in self.ratings.iter_mut() {
+            if !scores.iter().any(|(player_id, _)| player_id == id) {
+                rating.deviation =
+                    (rating.phi().powi(2) + rating.volatility.powi(2)).sqrt() * SCALE;
+            }
+        }
+
+        let updated = scores
+            .iter()
+            .map(|&(id, score)| {
+                let results = scores
+                    .iter()
+                    .filter(|&&(other_id, _)| other_id != id)
+                    .map(|&(other_id, other_score)| {
+                        let actual = match score.cmp(&other_score) {
+                            std::cmp::Ordering::Greater => 1.0,
+                            std::cmp::Ordering::Equal => 0.5,
+                            std::cmp::Ordering::Less => 0.0,
+                        };
+
+                        (self.rating(other_id), actual)
+                    })
+                    .collect::<Vec<_>>();
+
+                (id, update(self.rating(id), &results))
+            })
+            .collect::<Vec<_>>();
+
+        self.ratings.extend(updated);
+    }
+}
+
+/// Step 3 to 8 of http://www.glicko.net/glicko/glicko2.pdf
+fn update(player: GlickoRating, results: &[(GlickoRating, f64)]) -> GlickoRating {
+    let mu = player.mu();
+    let phi = player.phi();
+
+    let v = 1.0
+        / results
+            .iter()
+            .map(|(opponent, _)| {
+                let e = expected(mu, opponent.mu(), opponent.phi());
+                g(opponent.phi()).powi(2) * e * (1.0 - e)
+            })
+            .sum::<f64>();
+
+    let improvement: f64 = results
+        .iter()
+        .map(|(opponent, actual)| {
+            g(opponent.phi()) * (actual - expected(mu, opponent.mu(), opponent.phi()))
+        })
+        .sum();
+    let delta = v * improvement;
+
+    let volatility = new_volatility(player.volatility, phi, v, delta);
+    let phi_star = (phi.powi(2) + volatility.powi(2)).sqrt();
+    let new_phi = 1.0 / (1.0 / phi_star.powi(2) + 1.0 / v).sqrt();
+    let new_mu = mu + new_phi.powi(2) * improvement;
+
+    GlickoRating {
+        rating: new_mu * SCALE + INITIAL_RATING,
+        deviation: new_phi * SCALE,
+        volatility,
+    }
+}
+
+/// Finds the new volatility using the Illinois algorithm
+fn new_volatility(sigma: f64, phi: f64, v: f64, delta: f64) -> f64 {
+    let a = sigma.powi(2).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        ex * (delta.powi(2) - phi.powi(2) - v - ex) / (2.0 * (phi.powi(2) + v + ex).powi(2))
+            - (x - a) / TAU.powi(2)
+    };
+
+    let mut x_a = a;
+    let mut x_b = if delta.powi(2) > phi.powi(2) + v {
+        (delta.powi(2) - phi.powi(2) - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+
+    let mut f_a = f(x_a);
+    let mut f_b = f(x_b);
+    while (x_b - x_a).abs() > CONVERGENCE_TOLERANCE {
+        let x_c = x_a + (x_a - x_b) * f_a / (f_b - f_a);
+        let f_c = f(x_c);
+
+        if f_c * f_b <= 0.0 {
+            x_a = x_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+
+        x_b = x_c;
+        f_b = f_c;
+    }
+
+    (x_a / 2.0).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f64, deviation: f64) -> GlickoRating {
+        GlickoRating {
+            rating,
+            deviation,
+            volatility: INITIAL_VOLATILITY,
+        }
+    }
+
+    /// Worked example from section 3 of http://www.glicko.net/glicko/glicko2.pdf
+    #[test]
+    fn matches_paper_example() {
+        let results = [
+            (rating(1400.0, 30.0), 1.0),
+            (rating(1550.0, 100.0), 0.0),
+            (rating(1700.0, 300.0), 0.0),
+        ];
+
+        let updated = update(rating(1500.0, 200.0), &results);
+        assert!((updated.rating - 1464.06).abs() < 0.01, "{updated:?}");
+        assert!((updated.deviation - 151.52).abs() < 0.01, "{updated:?}");
+        assert!(
+            (updated.volatility - 0.05999).abs() < 0.00001,
+            "{updated:?}"
+        );
+    }
+
+    #[test]
+    fn ignores_games_with_one_player() {
+        let mut glicko = Glicko::new();
+        glicko.play(&[(1, 10)]);
+        assert!(glicko.ratings().is_empty());
+    }
+
+    #[test]
+    fn head_to_head_is_symmetric() {
+        let mut glicko = Glicko::new();
+        glicko.play(&[(1, 15), (2, 9)]);
+
+        let (winner, loser) = (glicko.rating(1), glicko.rating(2));
+        assert!(winner.rating > INITIAL_RATING);
+        assert!((winner.rating - INITIAL_RATING + loser.rating - INITIAL_RATING).abs() < 1e-9);
+        assert!((winner.deviation - loser.deviation).abs() < 1e-9);
+        assert!(winner.deviation < INITIAL_DEVIATION);
+    }
+
+    #[test]
+    fn tie_between_equal_players_only_reduces_deviation() {
+        let mut glicko = Glicko::new();
+        glicko.play(&[(1, 12), (2, 12)]);
+
+        for id in [1, 2] {
+            let rating = glicko.rating(id);
+            assert!((rating.rating - INITIAL_RATING).abs() < 1e-9);
+            assert!(rating.deviation < INITIAL_DEVIATION);
+        }
+    }
+
+    #[test]
+    fn players_sitting_out_become_less_certain() {
+        let mut glicko = Glicko::new();
+        glicko.play(&[(1, 15), (2, 9), (3, 3)]);
+        let before = glicko.rating(3);
+
+        glicko.play(&[(1, 15), (2, 9)]);
+        let after = glicko.rating(3);
+        assert_eq!(after.rating, before.rating);
+        assert!(after.deviation > before.deviation);
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use itertools::Itertools;
+use sqlx::{postgres::PgExecutor, Error, PgConnection, PgPool};
+
+use crate::utils::DateRange;
+use glicko::{Glicko, GlickoRating};
+
+pub mod elo;
+pub mod glicko;
+
+pub struct RatedGame {
+    pub id: i32,
+    pub date: NaiveDateTime,
+    pub scores: Vec<(i32, i32)>, // (Player ID, score)
+}
+
+/// All games in a group, oldest first, ready to be replayed through a rating system
+pub async fn get_rated_games<'c>(
+    executor: impl PgExecutor<'c>,
+    group_id: i32,
+    range: DateRange,
+) -> Result<Vec<RatedGame>, Error> {
+    let rows = sqlx::query!(
+        r#"SELECT game.id, game.date as "date: NaiveDateTime", game_score.player_id, game_score.score
+        FROM game
+        INNER JOIN game_score ON game_score.game_id = game.id
+        WHERE game.group_id = $1
+            AND ($2::timestamp IS NULL OR game.date >= $2)
+            AND ($3::timestamp IS NULL OR game.date < $3)
+        ORDER BY game.date ASC, game.id ASC"#,
+        group_id,
+        range.from as _,
+        range.to as _,
+    )
+    .fetch_all(executor)
+    .await?;
+
+    let games = rows
+        .into_iter()
+        .chunk_by(|row| (row.id, row.date))
+        .into_iter()
+        .map(|((id, date), scores)| RatedGame {
+            id,
+            date,
+            scores: scores.map(|s| (s.player_id, s.score)).collect(),
+        })
+        .collect();
+
+    Ok(games)
+}
+
+pub async fn get_elo_config(pool: &PgPool, group_id: i32) -> Result<elo::EloConfig, Error> {
+    let group = sqlx::query!(
+        "SELECT elo_k_factor, elo_initial_rating FROM grp WHERE id = $1",
+        group_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(elo::EloConfig {
+        k_factor: group.elo_k_factor,
+        initial_rating: group.elo_initial_rating,
+    })
+}
+
+/// Replays every game in the group and stores the resulting Glicko-2 ratings. Should be called
+/// whenever a group's games change. Locks the group until the transaction ends, so concurrent
+/// updates replay the games one after the other rather than overwriting each other
+pub async fn update_stored_ratings(conn: &mut PgConnection, group_id: i32) -> Result<(), Error> {
+    // Not `FOR UPDATE`, which would wait for transactions that have added a game to the group
+    sqlx::query!(
+        "SELECT id FROM grp WHERE id = $1 FOR NO KEY UPDATE",
+        group_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let mut glicko = Glicko::new();
+    for game in get_rated_games(&mut *conn, group_id, DateRange::default()).await? {
+        glicko.play(&game.scores);
+    }
+
+    sqlx::query!("DELETE FROM player_rating WHERE group_id = $1", group_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for (player_id, rating) in glicko.ratings() {
+        sqlx::query!(
+            "INSERT INTO player_rating (player_id, group_id, rating, deviation, volatility)
+            VALUES ($1, $2, $3, $4, $5)",
+            player_id,
+            group_id,
+            rating.rating,
+            rating.deviation,
+            rating.volatility,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Ratings stored by `update_stored_ratings`. Empty if the group's games haven't changed since
+/// ratings started being stored, until `tools recompute-ratings` is run
+pub async fn get_stored_ratings(
+    pool: &PgPool,
+    group_id: i32,
+) -> Result<HashMap<i32, GlickoRating>, Error> {
+    let ratings = sqlx::query!(
+        "SELECT player_id, rating, deviation, volatility FROM player_rating WHERE group_id = $1",
+        group_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| {
+        let rating = GlickoRating {
+            rating: r.rating,
+            deviation: r.deviation,
+            volatility: r.volatility,
+        };
+
+        (r.player_id, rating)
+    })
+    .collect();
+
+    Ok(ratings)
+}
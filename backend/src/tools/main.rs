@@ -1,7 +1,32 @@
-use std::io::{self, Write};
+use std::{
+    env,
+    fmt::Display,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
 
+use backend::{
+    badges::DEFAULT_BADGE_TIERS,
+    jwt::{KEY_EXTENSION, LEGACY_KID},
+    passwords,
+    ratings::update_stored_ratings,
+};
 use bcrypt::DEFAULT_COST;
-use clap::Parser;
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use rand::RngCore;
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migrator},
+    postgres::PgPoolOptions,
+    PgPool,
+};
+
+/// Size of generated JWT keys in bytes
+const JWT_KEY_SIZE: usize = 256;
+
+/// Migrations in `migrations/`, embedded at compile time
+static MIGRATOR: Migrator = sqlx::migrate!();
 
 fn generate_hashed_password(password: &str) -> String {
     bcrypt::hash(password, DEFAULT_COST).unwrap()
@@ -11,34 +36,750 @@ fn generate_hashed_password(password: &str) -> String {
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 enum Command {
-    HashPassword {
-        /// Password to hash
+    /// Hash a password, read from stdin (asked for if stdin is a terminal)
+    HashPassword,
+    /// Generate a new JWT signing key. The API must be restarted to start using it
+    RotateJwtKey {
+        /// Directory containing the keys. Defaults to `JWT_KEY_DIR`, or `jwt_keys` if not set
+        #[arg(long)]
+        dir: Option<PathBuf>,
+
+        /// Number of keys to keep, including the new one. Older keys are deleted, which
+        /// invalidates any tokens signed with them. The legacy key is never deleted
+        #[arg(long, default_value_t = 2)]
+        keep: usize,
+    },
+    /// Turn off two-factor authentication for an admin who has lost their authenticator app and
+    /// recovery codes. Also logs them out everywhere
+    #[command(name = "reset-2fa")]
+    ResetTwoFactor {
+        /// Username of the admin
+        #[arg(index = 1)]
+        username: String,
+    },
+    /// Find orphaned scores, games and memberships, and duplicate memberships, which stop the
+    /// integrity constraints being added
+    CheckIntegrity {
+        /// Delete orphaned scores and memberships, and duplicate memberships (keeping the oldest)
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Create a site admin (who can manage other admins) and print their ID
+    CreateAdmin {
+        /// Username of the admin
+        #[arg(index = 1)]
+        username: String,
+    },
+    /// Create a group and print its ID
+    CreateGroup {
+        /// Name of the group
         #[arg(index = 1)]
-        password: Option<String>,
+        name: String,
+
+        /// Highest score a player can get in a game. Needed for badges
+        #[arg(long)]
+        max_score: Option<i32>,
+
+        /// Hide the group from everyone without a role in it
+        #[arg(long)]
+        private: bool,
+
+        /// Username of the admin to make the group's owner. Without one, no admin can change the
+        /// group
+        #[arg(long)]
+        owner: Option<String>,
+    },
+    /// Create a player and print their ID
+    CreatePlayer {
+        /// Name of the player
+        #[arg(index = 1)]
+        name: String,
+    },
+    /// Add a player to a group
+    AddMember {
+        /// ID of the group
+        #[arg(index = 1)]
+        group_id: i32,
+
+        /// ID of the player
+        #[arg(index = 2)]
+        player_id: i32,
+    },
+    /// Remove a player from a group. Their games are kept
+    RemoveMember {
+        /// ID of the group
+        #[arg(index = 1)]
+        group_id: i32,
+
+        /// ID of the player
+        #[arg(index = 2)]
+        player_id: i32,
+    },
+    /// List groups with their members
+    ListGroups,
+    /// Replay every game and store the resulting ratings. Needed for groups with games from before
+    /// ratings were stored
+    RecomputeRatings {
+        /// ID of the group. Every group if not given
+        #[arg(index = 1)]
+        group_id: Option<i32>,
+    },
+    /// Manage database migrations
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
     },
 }
 
-fn ask_user_for_password() -> String {
+#[derive(Subcommand, Debug)]
+enum MigrateCommand {
+    /// Apply all pending migrations
+    Up,
+    /// List migrations and whether they have been applied
+    Status,
+    /// Undo the most recently applied migration
+    Revert,
+}
+
+/// Reads a password from stdin rather than the arguments, which other users can see in the
+/// process list and which end up in shell history. Only prompts if stdin is a terminal
+fn read_password() -> String {
     let mut pass = String::new();
-    print!("Password: ");
-    io::stdout().flush().unwrap();
+    if io::stdin().is_terminal() {
+        // Prompt on stderr, so stdout is only the output
+        eprint!("Password: ");
+        io::stderr().flush().unwrap();
+    }
     io::stdin().read_line(&mut pass).unwrap();
 
-    return pass;
+    pass.trim().to_string()
+}
+
+/// Writes a new key named after the current time (so the newest key sorts last) and deletes
+/// all but the newest `keep` keys. Returns the new key ID
+fn rotate_jwt_key(dir: &Path, keep: usize) -> io::Result<String> {
+    fs::create_dir_all(dir)?;
+
+    let mut key = vec![0; JWT_KEY_SIZE];
+    rand::thread_rng().fill_bytes(&mut key);
+
+    let kid = Utc::now().format("%Y%m%d%H%M%S").to_string();
+    fs::write(dir.join(format!("{kid}.{KEY_EXTENSION}")), key)?;
+
+    let mut keys = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    keys.retain(|path| {
+        path.extension().is_some_and(|ext| ext == KEY_EXTENSION)
+            && path.file_stem().is_some_and(|kid| kid != LEGACY_KID)
+    });
+    keys.sort();
+
+    let old = keys.len().saturating_sub(keep.max(1));
+    for path in &keys[..old] {
+        fs::remove_file(path)?;
+    }
+
+    Ok(kid)
+}
+
+fn exit_with_error(message: impl Display) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
+}
+
+/// Name of the constraint that a query violated, if any
+fn violated_constraint(e: &sqlx::Error) -> Option<&str> {
+    match e {
+        sqlx::Error::Database(e) => e.constraint(),
+        _ => None,
+    }
+}
+
+async fn connect() -> PgPool {
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&db_url)
+        .await
+        .unwrap()
+}
+
+async fn create_admin(pool: &PgPool, username: &str, password: &str) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar!(
+        "INSERT INTO admin_user (username, password_hash, site_admin) VALUES ($1, $2, true) RETURNING id",
+        username,
+        generate_hashed_password(password),
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Creates the group with the default badge tiers. Returns None if there is no admin called
+/// `owner`
+async fn create_group(
+    pool: &PgPool,
+    name: &str,
+    max_score: Option<i32>,
+    private: bool,
+    owner: Option<&str>,
+) -> Result<Option<i32>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let owner_id = match owner {
+        Some(owner) => {
+            let owner_id =
+                sqlx::query_scalar!("SELECT id FROM admin_user WHERE username = $1", owner)
+                    .fetch_optional(&mut *transaction)
+                    .await?;
+
+            match owner_id {
+                Some(owner_id) => Some(owner_id),
+                None => return Ok(None),
+            }
+        }
+        None => None,
+    };
+
+    let group_id = sqlx::query_scalar!(
+        "INSERT INTO grp (name, max_score, private) VALUES ($1, $2, $3) RETURNING id",
+        name,
+        max_score,
+        private,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    for (tier, emoji, fraction) in DEFAULT_BADGE_TIERS {
+        sqlx::query!(
+            "INSERT INTO badge_tier (group_id, name, emoji, fraction) VALUES ($1, $2, $3, $4)",
+            group_id,
+            tier,
+            emoji,
+            fraction,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    if let Some(owner_id) = owner_id {
+        sqlx::query!(
+            "INSERT INTO group_role (user_id, group_id, role) VALUES ($1, $2, 'owner')",
+            owner_id,
+            group_id,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(Some(group_id))
+}
+
+async fn create_player(pool: &PgPool, name: &str) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar!("INSERT INTO player (name) VALUES ($1) RETURNING id", name)
+        .fetch_one(pool)
+        .await
+}
+
+async fn add_member(pool: &PgPool, group_id: i32, player_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO player_group (player_id, group_id) VALUES ($1, $2)",
+        player_id,
+        group_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns false if the player isn't in the group
+async fn remove_member(pool: &PgPool, group_id: i32, player_id: i32) -> bool {
+    sqlx::query!(
+        "DELETE FROM player_group WHERE player_id = $1 AND group_id = $2",
+        player_id,
+        group_id
+    )
+    .execute(pool)
+    .await
+    .unwrap()
+    .rows_affected()
+        > 0
+}
+
+/// Returns the number of groups recomputed, which is 0 if the group doesn't exist
+async fn recompute_ratings(pool: &PgPool, group_id: Option<i32>) -> usize {
+    let group_ids = sqlx::query_scalar!(
+        "SELECT id FROM grp WHERE $1::int IS NULL OR id = $1 ORDER BY id",
+        group_id
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    for group_id in &group_ids {
+        let mut transaction = pool.begin().await.unwrap();
+        update_stored_ratings(&mut transaction, *group_id)
+            .await
+            .unwrap();
+        transaction.commit().await.unwrap();
+    }
+
+    group_ids.len()
+}
+
+async fn list_groups(pool: &PgPool) {
+    let groups = sqlx::query!("SELECT id, name, max_score, archived, private FROM grp ORDER BY id")
+        .fetch_all(pool)
+        .await
+        .unwrap();
+
+    let members = sqlx::query!(
+        "SELECT player_group.group_id, player.id, player.name
+        FROM player_group
+        JOIN player ON player.id = player_group.player_id
+        ORDER BY player.name"
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap();
+
+    for group in groups {
+        let mut flags = Vec::new();
+        if let Some(max_score) = group.max_score {
+            flags.push(format!("max score {max_score}"));
+        }
+        if group.archived {
+            flags.push("archived".to_string());
+        }
+        if group.private {
+            flags.push("private".to_string());
+        }
+
+        match flags.is_empty() {
+            true => println!("{} {}", group.id, group.name),
+            false => println!("{} {} ({})", group.id, group.name, flags.join(", ")),
+        }
+
+        for member in members.iter().filter(|m| m.group_id == group.id) {
+            println!("    {} {}", member.id, member.name);
+        }
+    }
+}
+
+/// Returns false if there is no admin with the username
+async fn reset_two_factor(pool: &PgPool, username: &str) -> bool {
+    let mut transaction = pool.begin().await.unwrap();
+
+    let user_id = sqlx::query_scalar!(
+        "UPDATE admin_user
+        SET totp_enabled = false, totp_secret = NULL, totp_last_step = NULL
+        WHERE username = $1
+        RETURNING id",
+        username
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .unwrap();
+
+    let Some(user_id) = user_id else {
+        return false;
+    };
+
+    sqlx::query!(
+        "DELETE FROM admin_recovery_code WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .unwrap();
+
+    sqlx::query!("DELETE FROM admin_session WHERE user_id = $1", user_id)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+    transaction.commit().await.unwrap();
+    true
+}
+
+/// Versions and checksums of the migrations that have been applied, oldest first
+async fn applied_migrations(pool: &PgPool) -> Result<Vec<(i64, Vec<u8>)>, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+
+    let mut applied: Vec<_> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m.checksum.into_owned()))
+        .collect();
+    applied.sort();
+
+    Ok(applied)
+}
+
+async fn migrate_up(pool: &PgPool) -> Result<(), MigrateError> {
+    let applied = applied_migrations(pool).await?;
+    let pending = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .filter(|m| !applied.iter().any(|(version, _)| *version == m.version))
+        .count();
+
+    MIGRATOR.run(pool).await?;
+
+    match pending {
+        0 => println!("Database is up to date"),
+        n => println!("Applied {n} migration(s)"),
+    }
+    Ok(())
+}
+
+async fn migrate_status(pool: &PgPool) -> Result<(), MigrateError> {
+    let applied = applied_migrations(pool).await?;
+
+    for migration in MIGRATOR.iter() {
+        if migration.migration_type.is_down_migration() {
+            continue;
+        }
+
+        let checksum = applied
+            .iter()
+            .find(|(version, _)| *version == migration.version)
+            .map(|(_, checksum)| checksum);
+
+        let status = match checksum {
+            None => "pending",
+            Some(checksum) if *checksum != *migration.checksum => "applied (changed since)",
+            Some(_) => "applied",
+        };
+        println!(
+            "{} {:<40} {status}",
+            migration.version, migration.description
+        );
+    }
+
+    Ok(())
+}
+
+/// Reverts the latest applied migration, if it has a down migration
+async fn migrate_revert(pool: &PgPool) -> Result<(), MigrateError> {
+    let applied = applied_migrations(pool).await?;
+    let Some((latest, _)) = applied.last() else {
+        println!("No migrations have been applied");
+        return Ok(());
+    };
+
+    let Some(down) = MIGRATOR
+        .iter()
+        .find(|m| m.version == *latest && m.migration_type.is_down_migration())
+    else {
+        eprintln!("Migration {latest} has no down migration, so can't be reverted");
+        std::process::exit(1);
+    };
+
+    // Everything newer than the previous migration is reverted, which is just the latest one
+    let target = applied
+        .iter()
+        .rev()
+        .nth(1)
+        .map_or(0, |(version, _)| *version);
+    MIGRATOR.undo(pool, target).await?;
+
+    println!("Reverted {} {}", down.version, down.description);
+    Ok(())
+}
+
+/// Prints problems that would stop the integrity constraints being added, and deletes the ones
+/// that can be repaired if `repair` is set. Returns the number of problems left
+async fn check_integrity(pool: &PgPool, repair: bool) -> usize {
+    let mut transaction = pool.begin().await.unwrap();
+    let mut remaining = 0;
+
+    let orphaned_scores = sqlx::query!(
+        r#"SELECT
+            game_score.id,
+            game_score.game_id,
+            game_score.player_id,
+            game.id IS NULL as "missing_game!",
+            player.id IS NULL as "missing_player!"
+        FROM game_score
+        LEFT JOIN game ON game.id = game_score.game_id
+        LEFT JOIN player ON player.id = game_score.player_id
+        WHERE game.id IS NULL OR player.id IS NULL
+        ORDER BY game_score.id"#
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .unwrap();
+
+    for score in &orphaned_scores {
+        if score.missing_game {
+            println!("Score {} is for missing game {}", score.id, score.game_id);
+        }
+        if score.missing_player {
+            println!(
+                "Score {} is for missing player {}",
+                score.id, score.player_id
+            );
+        }
+    }
+
+    let orphaned_memberships = sqlx::query!(
+        r#"SELECT
+            player_group.id,
+            player_group.player_id,
+            player_group.group_id,
+            player.id IS NULL as "missing_player!",
+            grp.id IS NULL as "missing_group!"
+        FROM player_group
+        LEFT JOIN player ON player.id = player_group.player_id
+        LEFT JOIN grp ON grp.id = player_group.group_id
+        WHERE player.id IS NULL OR grp.id IS NULL
+        ORDER BY player_group.id"#
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .unwrap();
+
+    for membership in &orphaned_memberships {
+        if membership.missing_player {
+            println!(
+                "Membership {} is for missing player {}",
+                membership.id, membership.player_id
+            );
+        }
+        if membership.missing_group {
+            println!(
+                "Membership {} is for missing group {}",
+                membership.id, membership.group_id
+            );
+        }
+    }
+
+    let duplicate_memberships = sqlx::query!(
+        r#"SELECT player_id, group_id, COUNT(*) as "count!"
+        FROM player_group
+        GROUP BY player_id, group_id
+        HAVING COUNT(*) > 1
+        ORDER BY group_id, player_id"#
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .unwrap();
+
+    for membership in &duplicate_memberships {
+        println!(
+            "Player {} is in group {} {} times",
+            membership.player_id, membership.group_id, membership.count
+        );
+    }
+
+    // Games can't be repaired automatically, as they might belong to a group that was deleted
+    // by mistake
+    let orphaned_games = sqlx::query!(
+        "SELECT game.id, game.group_id
+        FROM game
+        LEFT JOIN grp ON grp.id = game.group_id
+        WHERE grp.id IS NULL
+        ORDER BY game.id"
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .unwrap();
+
+    for game in &orphaned_games {
+        println!("Game {} is in missing group {}", game.id, game.group_id);
+    }
+    remaining += orphaned_games.len();
+
+    let repairable =
+        orphaned_scores.len() + orphaned_memberships.len() + duplicate_memberships.len();
+    if !repair {
+        return remaining + repairable;
+    }
+
+    let score_ids: Vec<_> = orphaned_scores.iter().map(|s| s.id).collect();
+    sqlx::query!("DELETE FROM game_score WHERE id = ANY($1)", &score_ids)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+    let membership_ids: Vec<_> = orphaned_memberships.iter().map(|m| m.id).collect();
+    sqlx::query!(
+        "DELETE FROM player_group WHERE id = ANY($1)",
+        &membership_ids
+    )
+    .execute(&mut *transaction)
+    .await
+    .unwrap();
+
+    sqlx::query!(
+        "DELETE FROM player_group duplicate
+        USING player_group original
+        WHERE duplicate.player_id = original.player_id
+            AND duplicate.group_id = original.group_id
+            AND duplicate.id > original.id"
+    )
+    .execute(&mut *transaction)
+    .await
+    .unwrap();
+
+    transaction.commit().await.unwrap();
+    if repairable > 0 {
+        println!("Repaired {repairable} problem(s)");
+    }
+
+    remaining
 }
 
-fn main() {
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
     let command = Command::parse();
 
     match command {
-        Command::HashPassword { password } => {
-            let password = match password {
-                Some(password) => password,
-                None => ask_user_for_password(),
+        Command::HashPassword => {
+            let hash = generate_hashed_password(&read_password());
+            println!("{hash}");
+        }
+        Command::RotateJwtKey { dir, keep } => {
+            let dir = dir.unwrap_or_else(|| {
+                env::var("JWT_KEY_DIR")
+                    .unwrap_or("jwt_keys".to_string())
+                    .into()
+            });
+
+            let kid = rotate_jwt_key(&dir, keep).unwrap();
+            println!("Created key {kid} in {}", dir.display());
+        }
+        Command::ResetTwoFactor { username } => {
+            let pool = connect().await;
+            if reset_two_factor(&pool, &username).await {
+                println!("Reset two-factor authentication for {username}");
+            } else {
+                exit_with_error(format!("No admin called {username}"));
+            }
+        }
+        Command::CreateAdmin { username } => {
+            let username = username.trim();
+            if username.is_empty() {
+                exit_with_error("Username cannot be empty");
+            }
+
+            let password = read_password();
+            if let Some(error) = passwords::policy_errors(username, &password).first() {
+                exit_with_error(error);
+            }
+
+            let pool = connect().await;
+            match create_admin(&pool, username, &password).await {
+                Ok(id) => println!("{id}"),
+                Err(e) if violated_constraint(&e) == Some("username_unique") => {
+                    exit_with_error(format!("There is already an admin called {username}"))
+                }
+                Err(e) => panic!("{e}"),
+            }
+        }
+        Command::CreateGroup {
+            name,
+            max_score,
+            private,
+            owner,
+        } => {
+            let name = name.trim();
+            if name.is_empty() {
+                exit_with_error("Name cannot be empty");
+            }
+            if max_score.is_some_and(|max_score| max_score <= 0) {
+                exit_with_error("Max score must be more than 0");
+            }
+
+            let pool = connect().await;
+            match create_group(&pool, name, max_score, private, owner.as_deref()).await {
+                Ok(Some(id)) => println!("{id}"),
+                Ok(None) => exit_with_error(format!("No admin called {}", owner.unwrap())),
+                Err(e) => panic!("{e}"),
+            }
+        }
+        Command::CreatePlayer { name } => {
+            let name = name.trim();
+            if name.is_empty() {
+                exit_with_error("Name cannot be empty");
+            }
+
+            let pool = connect().await;
+            match create_player(&pool, name).await {
+                Ok(id) => println!("{id}"),
+                Err(e) if violated_constraint(&e) == Some("name_unique") => {
+                    exit_with_error(format!("There is already a player called {name}"))
+                }
+                Err(e) => panic!("{e}"),
+            }
+        }
+        Command::AddMember {
+            group_id,
+            player_id,
+        } => {
+            let pool = connect().await;
+            match add_member(&pool, group_id, player_id).await {
+                Ok(()) => println!("Added player {player_id} to group {group_id}"),
+                Err(e) => match violated_constraint(&e) {
+                    Some("player_group_unique") => exit_with_error(format!(
+                        "Player {player_id} is already in group {group_id}"
+                    )),
+                    Some("fk_player_id") => exit_with_error(format!("No player {player_id}")),
+                    Some("fk_group_id") => exit_with_error(format!("No group {group_id}")),
+                    _ => panic!("{e}"),
+                },
+            }
+        }
+        Command::RemoveMember {
+            group_id,
+            player_id,
+        } => {
+            let pool = connect().await;
+            if remove_member(&pool, group_id, player_id).await {
+                println!("Removed player {player_id} from group {group_id}");
+            } else {
+                exit_with_error(format!("Player {player_id} isn't in group {group_id}"));
+            }
+        }
+        Command::ListGroups => {
+            let pool = connect().await;
+            list_groups(&pool).await;
+        }
+        Command::RecomputeRatings { group_id } => {
+            let pool = connect().await;
+            match (recompute_ratings(&pool, group_id).await, group_id) {
+                (0, Some(group_id)) => exit_with_error(format!("No group {group_id}")),
+                (n, _) => println!("Recomputed ratings for {n} group(s)"),
+            }
+        }
+        Command::CheckIntegrity { repair } => {
+            let pool = connect().await;
+            match check_integrity(&pool, repair).await {
+                0 => println!("No problems remaining"),
+                n => {
+                    eprintln!("{n} problem(s) need fixing before the constraints can be added");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Migrate { command } => {
+            let pool = connect().await;
+            let result = match command {
+                MigrateCommand::Up => migrate_up(&pool).await,
+                MigrateCommand::Status => migrate_status(&pool).await,
+                MigrateCommand::Revert => migrate_revert(&pool).await,
             };
 
-            let hash = generate_hashed_password(password.trim());
-            println!("{hash}");
+            if let Err(e) = result {
+                eprintln!("Migration failed: {e}");
+                std::process::exit(1);
+            }
         }
     };
 }
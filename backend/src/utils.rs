@@ -0,0 +1,46 @@
+use std::ops::Range;
+
+use chrono::NaiveDateTime;
+
+pub fn std_dev(sum: f32, sum_of_squares: f32, n: i32) -> f32 {
+    let mean = sum / n as f32;
+    let mean_squares = sum_of_squares / n as f32;
+    (mean_squares - mean.powi(2)).sqrt()
+}
+
+/// Indices of the n scores in a row with the highest total, or `None` if there are fewer than n
+pub fn best_window(scores: &[i32], n: usize) -> Option<Range<usize>> {
+    if n == 0 {
+        return None;
+    }
+
+    scores
+        .windows(n)
+        .enumerate()
+        .max_by(|(_, w1), (_, w2)| w1.iter().sum::<i32>().cmp(&w2.iter().sum::<i32>()))
+        .map(|(i, _)| i..i + n)
+}
+
+/// Games on or after `from` and before `to`. Unbounded where `None`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DateRange {
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+impl DateRange {
+    pub fn is_unbounded(&self) -> bool {
+        self.from.is_none() && self.to.is_none()
+    }
+
+    /// Only the dates covered by both ranges
+    pub fn intersect(self, other: DateRange) -> DateRange {
+        DateRange {
+            from: self.from.max(other.from),
+            to: match (self.to, other.to) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+}
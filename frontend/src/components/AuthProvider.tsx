@@ -11,12 +11,49 @@ function loadStoredTokens(): Tokens {
   return { access, refresh };
 }
 
-async function authenticate(name: string, password: string): Promise<Tokens> {
-  const resp = await ax.post("/auth", { name, password });
+async function authenticate(name: string, password: string, code: string): Promise<Tokens> {
+  let resp = await ax.post("/auth", { name, password });
+
+  // Admins with two-factor authentication need to give a code as well
+  if (resp.data.two_factor_token) {
+    resp = await ax.post("/auth/2fa/verify", { twoFactorToken: resp.data.two_factor_token, code });
+  }
+
   return { access: resp.data.access_token, refresh: resp.data.refresh_token };
 }
 
-function configureAxios(tokens: Tokens, onUpdateAccessToken: (token: string) => void, onFailedReauth: () => void) {
+// Only keep one refresh interceptor, otherwise old ones would try to reuse stale refresh tokens
+let refreshInterceptor: number | null = null;
+
+// Requests that fail together share one refresh, as each refresh token can only be used once
+let pendingRefresh: Promise<string | null> | null = null;
+
+function refreshAccessToken(
+  refresh: string | null,
+  onUpdateTokens: (tokens: Tokens) => void,
+  onFailedReauth: () => void,
+): Promise<string | null> {
+  if (!pendingRefresh) {
+    pendingRefresh = ax.get("/auth/refresh", { headers: { 'Authorization': `Bearer ${refresh}` } })
+      .then((resp) => {
+        // Refresh tokens are single use, so store the new one as well
+        const newTokens = { access: resp.data.access_token, refresh: resp.data.refresh_token };
+        onUpdateTokens(newTokens);
+        return newTokens.access;
+      })
+      .catch(() => {
+        onFailedReauth();
+        return null;
+      })
+      .finally(() => {
+        pendingRefresh = null;
+      });
+  }
+
+  return pendingRefresh;
+}
+
+function configureAxios(tokens: Tokens, onUpdateTokens: (tokens: Tokens) => void, onFailedReauth: () => void) {
   // Set up access token header
   if (tokens.access) {
     ax.defaults.headers.common['Authorization'] = `Bearer ${tokens.access}`;
@@ -25,7 +62,11 @@ function configureAxios(tokens: Tokens, onUpdateAccessToken: (token: string) =>
   }
 
   // Set up auto refresh token
-  ax.interceptors.response.use(
+  if (refreshInterceptor !== null) {
+    ax.interceptors.response.eject(refreshInterceptor);
+  }
+
+  refreshInterceptor = ax.interceptors.response.use(
     (resp) => {
       return resp;
     },
@@ -35,15 +76,12 @@ function configureAxios(tokens: Tokens, onUpdateAccessToken: (token: string) =>
         error?.config?.url?.trim() !== "/auth/refresh" &&
         error?.config?.url?.trim() !== "/auth"
       ) {
-        try {
-          const newToken = (await ax.get("/auth/refresh", { headers: { 'Authorization': `Bearer ${tokens.refresh}` } })).data;
-          error.config.headers['Authorization'] = `Bearer ${newToken}`;
-          onUpdateAccessToken(newToken);
-        } catch (error) {
-          onFailedReauth();
+        const access = await refreshAccessToken(tokens.refresh, onUpdateTokens, onFailedReauth);
+        if (!access) {
           return;
         }
 
+        error.config.headers['Authorization'] = `Bearer ${access}`;
         return await ax.request(error.config);
       } else {
         throw error;
@@ -54,12 +92,12 @@ function configureAxios(tokens: Tokens, onUpdateAccessToken: (token: string) =>
 
 const AuthContext = createContext<{
   isAuthenticated: boolean,
-  authenticate: (name: string, password: string) => Promise<boolean>,
+  authenticate: (name: string, password: string, code: string) => Promise<boolean>,
   logout: () => void
   username: string | null,
 }>({
   isAuthenticated: false,
-  authenticate: (_: string, __: string) => new Promise(() => false),
+  authenticate: (_: string, __: string, ___: string) => new Promise(() => false),
   logout: () => { },
   username: null,
 });
@@ -83,7 +121,7 @@ const AuthProvider = ({ children }: { children: React.ReactNode }) => {
   // Load tokens from localstorage
   useEffect(() => {
     const tokens = loadStoredTokens();
-    configureAxios(tokens, (access) => updateTokens({ ...tokens, access }), logout);
+    configureAxios(tokens, updateTokens, logout);
     setTokens(tokens);
   }, [])
 
@@ -100,13 +138,13 @@ const AuthProvider = ({ children }: { children: React.ReactNode }) => {
       store.remove("refresh_token");
     }
 
-    configureAxios(tokens, (access) => updateTokens({ ...tokens, access }), logout);
+    configureAxios(tokens, updateTokens, logout);
     setTokens(tokens);
   }
 
-  const authenticateAndUpdate = async (name: string, password: string) => {
+  const authenticateAndUpdate = async (name: string, password: string, code: string) => {
     try {
-      updateTokens(await authenticate(name, password));
+      updateTokens(await authenticate(name, password, code));
 
       return true;
     } catch (Error) {
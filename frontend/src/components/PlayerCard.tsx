@@ -16,6 +16,18 @@ interface ChangeIconProps {
   className?: string;
 }
 
+const BadgeList: React.FC<{ badges: Badges }> = ({ badges }) => {
+  return (
+    <>
+      {badges
+        .filter((badge) => badge.count > 0)
+        .map((badge) => (
+          <Badge n={badge.count} icon={badge.emoji} key={badge.name} />
+        ))}
+    </>
+  );
+};
+
 const ChangeIcon: React.FC<ChangeIconProps> = ({ change, className }) => {
   return (
     <IconContext.Provider value={{ size: "20px", className }}>
@@ -51,10 +63,7 @@ export const PlayerCardWithChange: React.FC<PlayerCardWithChangeProps> = ({
       </div>
       <div className="grow pr-4 whitespace-nowrap flex-row flex">
         <p>{name}</p>
-        {badges.star > 0 && <Badge n={badges.star} icon="🎖️" />}
-        {badges.gold > 0 && <Badge n={badges.gold} icon="🥇" />}
-        {badges.silver > 0 && <Badge n={badges.silver} icon="🥈" />}
-        {badges.bronze > 0 && <Badge n={badges.bronze} icon="🥉" />}
+        <BadgeList badges={badges} />
       </div>
       <p className="w-20 hidden xl:block">{games}</p>
       <p className="w-20 hidden sm:block">{wins}</p>
@@ -96,10 +105,7 @@ export const PlayerCard: React.FC<PlayerCardProps> = ({
         <p>{name}</p>
         {badges && (
           <div>
-            {badges.star > 0 && <Badge n={badges.star} icon="🎖️" />}
-            {badges.gold > 0 && <Badge n={badges.gold} icon="🥇" />}
-            {badges.silver > 0 && <Badge n={badges.silver} icon="🥈" />}
-            {badges.bronze > 0 && <Badge n={badges.bronze} icon="🥉" />}
+            <BadgeList badges={badges} />
           </div>
         )}
       </div>
@@ -15,18 +15,11 @@ export interface Group {
   archived: boolean;
 }
 
-export interface Badges {
-  star: number;
-  gold: number;
-  silver: number;
-  bronze: number;
+export interface BadgeCount {
+  name: string;
+  emoji: string;
+  count: number;
 }
 
-export function noBadges() {
-  return {
-    star: 0,
-    gold: 0,
-    silver: 0,
-    bronze: 0,
-  } as Badges;
-}
+// Best tier first
+export type Badges = BadgeCount[];
@@ -6,7 +6,7 @@ import {
   getPlayerStats,
 } from "../data/playerStats";
 import { Link, useLoaderData } from "react-router-dom";
-import { Badges, Group, noBadges } from "../data/types";
+import { Badges, Group } from "../data/types";
 import Page from "../components/Page";
 import HeaderBar, { Sort, getSort } from "../components/HeaderBar";
 import NumberGamesSelector, { NumberGames } from "../components/NumberGames";
@@ -133,7 +133,7 @@ const Scoreboard = () => {
                 stats={p}
                 idx={i}
                 key={p.stats.id}
-                badges={badges.get(p.stats.id) ?? noBadges()}
+                badges={badges.get(p.stats.id) ?? []}
               />
             </Link>
           </div>
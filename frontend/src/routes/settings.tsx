@@ -21,7 +21,7 @@ const Settings = () => {
       await ax.post("/player", { name: playerName });
     } catch (error) {
       if (axios.isAxiosError(error)) {
-        alert(error.response?.data)
+        alert(error.response?.data?.message)
         return;
       } else {
         throw error;
@@ -44,7 +44,7 @@ const Settings = () => {
     } catch (error) {
       if (axios.isAxiosError(error)) {
 
-        alert(error.response?.data)
+        alert(error.response?.data?.message)
         return;
       } else {
         throw error;
@@ -59,11 +59,12 @@ const Settings = () => {
   // Login
   const [userName, setUserName] = useState<string>("");
   const [userPassword, setUserPassword] = useState<string>("");
+  const [userCode, setUserCode] = useState<string>("");
 
   const handleLogin = async (e: { preventDefault: () => void }) => {
     e.preventDefault();
 
-    const success = await authenticate(userName, userPassword);
+    const success = await authenticate(userName, userPassword, userCode);
     if (!success) {
       alert("Failed to authenticate");
       return;
@@ -71,6 +72,7 @@ const Settings = () => {
 
     setUserName("");
     setUserPassword("");
+    setUserCode("");
   }
 
   // Graph tension
@@ -166,6 +168,19 @@ const Settings = () => {
                   />
                 </div>
 
+                <div className="flex flex-col">
+                  <label>Two-factor code (if enabled)</label>
+                  <input
+                    name="code"
+                    className="bg-gray-300 p-2 rounded-lg"
+                    placeholder="123456"
+                    required={false}
+                    value={userCode}
+                    autoComplete="one-time-code"
+                    onChange={(e) => setUserCode(e.target.value)}
+                  />
+                </div>
+
                 <button
                   className="px-4 py-2 rounded-lg transition bg-blue-500 text-white hover:bg-blue-400"
                   type="submit"